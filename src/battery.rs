@@ -0,0 +1,312 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::err::Result;
+use crate::input::{read_ahead, DataPoint};
+
+/// Cell counts of the packs commonly fitted to boards, smallest first.
+const CELL_COUNTS: &[u8] = &[15, 18, 20, 21];
+
+/// How far over a full cell's voltage the pack may read, just after charging.
+const OVERCHARGE: f32 = 0.05;
+
+/// The lowest state of charge (in %) the pack's highest voltage may be at for a cell count to be
+/// guessed, as the ride may not have started on a full charge.
+const GUESS_PERCENT: f32 = 80.0;
+
+/// How much of the start of the ride is read to guess the cell count, in seconds.
+const GUESS_SECONDS: f64 = 60.0;
+
+/// The type of cell the pack is built from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chemistry {
+    /// Molicel P42A.
+    P42a,
+    /// Molicel P45B.
+    P45b,
+    /// Samsung 50S.
+    S50,
+    /// Lithium iron phosphate.
+    Lfp,
+}
+
+impl Chemistry {
+    /// The cell's voltage at rest against its state of charge (in %), from empty to full.
+    fn curve(&self) -> &'static [(f32, f32)] {
+        match self {
+            Chemistry::P42a => &[
+                (2.50, 0.0),
+                (3.00, 2.0),
+                (3.30, 8.0),
+                (3.45, 20.0),
+                (3.55, 33.0),
+                (3.62, 45.0),
+                (3.70, 55.0),
+                (3.80, 65.0),
+                (3.90, 75.0),
+                (4.00, 85.0),
+                (4.10, 94.0),
+                (4.20, 100.0),
+            ],
+            Chemistry::P45b => &[
+                (2.50, 0.0),
+                (3.00, 3.0),
+                (3.30, 9.0),
+                (3.45, 19.0),
+                (3.56, 32.0),
+                (3.63, 44.0),
+                (3.71, 55.0),
+                (3.81, 66.0),
+                (3.92, 77.0),
+                (4.02, 87.0),
+                (4.11, 95.0),
+                (4.20, 100.0),
+            ],
+            Chemistry::S50 => &[
+                (2.50, 0.0),
+                (3.00, 4.0),
+                (3.30, 10.0),
+                (3.45, 21.0),
+                (3.55, 33.0),
+                (3.63, 45.0),
+                (3.71, 56.0),
+                (3.81, 66.0),
+                (3.91, 76.0),
+                (4.01, 86.0),
+                (4.10, 94.0),
+                (4.20, 100.0),
+            ],
+            Chemistry::Lfp => &[
+                (2.50, 0.0),
+                (3.00, 5.0),
+                (3.20, 10.0),
+                (3.25, 20.0),
+                (3.28, 30.0),
+                (3.30, 40.0),
+                (3.31, 50.0),
+                (3.32, 60.0),
+                (3.33, 70.0),
+                (3.34, 80.0),
+                (3.36, 90.0),
+                (3.45, 99.0),
+                (3.65, 100.0),
+            ],
+        }
+    }
+
+    /// The cell's internal resistance, in ohms, which the voltage sags by under load.
+    fn resistance(&self) -> f32 {
+        match self {
+            Chemistry::P42a => 0.015,
+            Chemistry::P45b => 0.010,
+            Chemistry::S50 => 0.013,
+            Chemistry::Lfp => 0.010,
+        }
+    }
+
+    /// The state of charge (in %) of a cell at rest at `voltage`.
+    pub fn percent(&self, voltage: f32) -> f32 {
+        let curve = self.curve();
+        match curve.iter().position(|(v, _)| voltage < *v) {
+            Some(0) => 0.0,
+            Some(i) => {
+                let (v1, p1) = curve[i - 1];
+                let (v2, p2) = curve[i];
+                p1 + (p2 - p1) * (voltage - v1) / (v2 - v1)
+            }
+            None => 100.0,
+        }
+    }
+}
+
+impl FromStr for Chemistry {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "p42a" => Ok(Chemistry::P42a),
+            "p45b" => Ok(Chemistry::P45b),
+            "50s" => Ok(Chemistry::S50),
+            "lfp" => Ok(Chemistry::Lfp),
+            _ => Err(format!(
+                "unknown battery {}, expected one of: p42a, p45b, 50s, lfp",
+                s
+            )),
+        }
+    }
+}
+
+/// A battery pack, used to work out its state of charge from its voltage.
+#[derive(Debug, Clone, Copy)]
+pub struct Pack {
+    pub chemistry: Chemistry,
+    /// Cells in series.
+    pub cells: u8,
+    /// Cells in parallel, which share the current.
+    pub parallel: u8,
+}
+
+impl Pack {
+    /// Fills in the point's state of charge, unless the log recorded it.
+    ///
+    /// The voltage is corrected for sag under load, using the current drawn and the cells' internal
+    /// resistance, to estimate what it would be at rest.
+    pub fn apply(&self, point: &mut DataPoint) {
+        if point.battery_percent.is_some() || point.batt_voltage.is_nan() {
+            return;
+        }
+
+        let current = if point.batt_current.is_nan() {
+            0.0
+        } else {
+            point.batt_current
+        };
+        let voltage = point.batt_voltage / self.cells as f32
+            + current / self.parallel as f32 * self.chemistry.resistance();
+        point.battery_percent = Some(self.chemistry.percent(voltage));
+    }
+}
+
+/// A cell count guessed from the pack's voltage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellGuess {
+    pub cells: u8,
+    /// The highest pack voltage seen, which the guess is based on.
+    pub voltage: f32,
+}
+
+impl CellGuess {
+    /// Guesses the cell count from the pack's highest voltage, which is taken to be when it's at its
+    /// fullest. The pack it's guessed to be is the one which would be close to full at that voltage.
+    ///
+    /// There's no guess if no pack would be, or if more than one would be, such as at 84 V which is
+    /// either a full 20s pack or a partly charged 21s one.
+    pub fn from_voltage(voltage: f32, chemistry: Chemistry) -> Option<CellGuess> {
+        let full = chemistry.curve().last().unwrap().0 + OVERCHARGE;
+        let mut counts = CELL_COUNTS.iter().filter(|&&cells| {
+            let cell = voltage / cells as f32;
+            cell <= full && chemistry.percent(cell) >= GUESS_PERCENT
+        });
+        match (counts.next(), counts.next()) {
+            (Some(&cells), None) => Some(CellGuess { cells, voltage }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CellGuess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Guessed a {}s battery from its highest voltage of {:.1} V ({:.2} V per cell), \
+             which the voltage per cell and charge shown depend on, use --cell-count if that's \
+             wrong",
+            self.cells,
+            self.voltage,
+            self.voltage / self.cells as f32
+        )
+    }
+}
+
+/// Reads the first minute of the ride to guess the pack's cell count, see [`read_ahead`].
+pub fn guess_cell_count(
+    samples: &mut impl Iterator<Item = Result<DataPoint>>,
+    chemistry: Chemistry,
+) -> (Option<CellGuess>, VecDeque<Result<DataPoint>>) {
+    let mut start = None;
+    let mut voltage = f32::NAN;
+    let read = read_ahead(samples, f64::INFINITY, |point| {
+        let start = *start.get_or_insert(point.time);
+        // max ignores NaN, for samples without a voltage
+        voltage = voltage.max(point.batt_voltage);
+        point.time - start >= GUESS_SECONDS
+    });
+
+    (CellGuess::from_voltage(voltage, chemistry), read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_voltage() {
+        let cells =
+            |voltage| CellGuess::from_voltage(voltage, Chemistry::P42a).map(|guess| guess.cells);
+        assert_eq!(cells(63.0), Some(15));
+        assert_eq!(cells(75.6), Some(18));
+        assert_eq!(cells(88.2), Some(21));
+        // a partly charged 18s, 20s and 21s pack
+        assert_eq!(cells(75.0), Some(18));
+        assert_eq!(cells(80.0), Some(20));
+        assert_eq!(cells(84.0), None);
+        // too far from full to tell
+        assert_eq!(cells(58.0), None);
+        assert_eq!(cells(20.0), None);
+        assert_eq!(cells(120.0), None);
+        assert_eq!(cells(f32::NAN), None);
+
+        let guess = CellGuess::from_voltage(66.0, Chemistry::Lfp);
+        assert_eq!(guess.map(|guess| guess.cells), Some(18));
+        // LFP's voltage hardly changes until it's nearly empty
+        assert_eq!(CellGuess::from_voltage(72.0, Chemistry::Lfp), None);
+    }
+
+    #[test]
+    fn state_of_charge() {
+        let close = |a: Option<f32>, b: f32| (a.unwrap() - b).abs() < 1e-3;
+        assert_eq!(Chemistry::P42a.percent(4.25), 100.0);
+        assert_eq!(Chemistry::P42a.percent(2.0), 0.0);
+        assert!(close(Some(Chemistry::P42a.percent(3.75)), 60.0));
+        assert!(close(Some(Chemistry::Lfp.percent(3.305)), 45.0));
+
+        let pack = Pack {
+            chemistry: Chemistry::P42a,
+            cells: 20,
+            parallel: 2,
+        };
+        let soc = |voltage, current, logged| {
+            let mut point = DataPoint {
+                batt_voltage: voltage,
+                batt_current: current,
+                battery_percent: logged,
+                ..Default::default()
+            };
+            pack.apply(&mut point);
+            point.battery_percent
+        };
+        assert!(close(soc(75.0, 0.0, None), 60.0));
+        assert!(close(soc(75.0, f32::NAN, None), 60.0));
+        // sagging by 0.15 V a cell under load
+        assert!(close(soc(72.0, 20.0, None), 60.0));
+        assert_eq!(soc(75.0, 0.0, Some(42.0)), Some(42.0));
+        assert_eq!(soc(f32::NAN, 0.0, None), None);
+    }
+
+    #[test]
+    fn read_ahead() {
+        let mut samples = (0..100).map(|t| {
+            Ok(DataPoint {
+                time: 10.0 + t as f64,
+                batt_voltage: if t == 5 { 82.0 } else { 80.0 },
+                ..Default::default()
+            })
+        });
+        let (guess, read) = guess_cell_count(&mut samples, Chemistry::P42a);
+        assert_eq!(
+            guess,
+            Some(CellGuess {
+                cells: 20,
+                voltage: 82.0
+            })
+        );
+        assert_eq!(read.len(), 61);
+        assert_eq!(samples.next().unwrap().unwrap().time, 71.0);
+
+        let samples: Vec<Result<DataPoint>> = vec![Ok(DataPoint::default()), Err("bad".into())];
+        let (guess, read) = guess_cell_count(&mut samples.into_iter(), Chemistry::P42a);
+        assert_eq!(guess, None);
+        assert!(read[1].is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::f32::consts::PI;
+
+use crate::input::{Coordinate, DataPoint};
+
+/// Mean radius of the earth, in metres.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Seconds a GPS position can stay the same for before the board is taken to have stopped.
+const STILL_SECONDS: f64 = 3.0;
+
+/// The parts of a board which relate motor speed to road speed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Board {
+    /// Pairs of magnet poles in the motor, which turns once every this many electrical revolutions.
+    pub pole_pairs: u8,
+    /// Metres.
+    pub tire_diameter: f32,
+}
+
+impl Board {
+    /// Road speed, in km/h, at the given electrical RPM.
+    pub fn speed(&self, erpm: f32) -> f32 {
+        let rpm = erpm.abs() / self.pole_pairs as f32;
+        rpm * PI * self.tire_diameter * 60.0 / 1000.0
+    }
+}
+
+/// Where the speed shown comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SpeedSource {
+    /// The speed the log recorded.
+    #[default]
+    Logged,
+    /// Worked out from the motor's ERPM.
+    Erpm(Board),
+    /// The GPS ground speed, or if the GPS doesn't report it, the speed between its positions.
+    Gps,
+}
+
+/// Great-circle distance between two coordinates, in metres.
+fn distance(a: &Coordinate, b: &Coordinate) -> f64 {
+    let (lat1, lat2) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (b.longitude - a.longitude).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+/// Replaces the speed of each point of a ride with one from a [`SpeedSource`].
+///
+/// Logs which don't record the GPS speed, such as Float Control's, repeat each GPS position until
+/// the next fix. Their speed is worked out from the distance and time between one position and the
+/// next, and held until the position changes again.
+pub struct Speeds {
+    source: SpeedSource,
+    /// When the GPS position last changed, and where to.
+    fix: Option<(f64, Coordinate)>,
+    /// Speed between the last two positions, in km/h.
+    fix_speed: Option<f32>,
+    /// Whether a sample without the source has been warned about.
+    warned: bool,
+}
+
+impl Speeds {
+    pub fn new(source: SpeedSource) -> Self {
+        Speeds {
+            source,
+            fix: None,
+            fix_speed: None,
+            warned: false,
+        }
+    }
+
+    /// Replaces the point's speed with one from the source, which is missing if the source is. The
+    /// first sample without the source is warned about.
+    pub fn apply(&mut self, point: &mut DataPoint) {
+        let (speed, missing) = match self.source {
+            SpeedSource::Logged => return,
+            SpeedSource::Erpm(board) => (
+                point.erpm.map(|erpm| board.speed(erpm)),
+                point.erpm.is_none().then_some("ERPM"),
+            ),
+            SpeedSource::Gps => (
+                self.gps_speed(point),
+                point.position.is_none().then_some("GPS position"),
+            ),
+        };
+        if let Some(missing) = missing.filter(|_| !self.warned) {
+            self.warned = true;
+            eprintln!(
+                "Warning: the log has no {} at {:.1} s, speed will be empty where it's missing",
+                missing, point.time
+            );
+        }
+
+        point.speed = speed.unwrap_or(f32::NAN);
+    }
+
+    fn gps_speed(&mut self, point: &DataPoint) -> Option<f32> {
+        let Some(position) = &point.position else {
+            // the next position can't be compared with one from before the GPS was lost
+            self.fix = None;
+            self.fix_speed = None;
+            return None;
+        };
+        if position.speed.is_some() {
+            return position.speed;
+        }
+
+        match self.fix {
+            Some((time, coordinate)) if coordinate == position.coordinate => {
+                if point.time - time > STILL_SECONDS {
+                    self.fix_speed = Some(0.0);
+                }
+            }
+            Some((time, coordinate)) => {
+                let seconds = point.time - time;
+                self.fix_speed = (seconds > 0.0)
+                    .then(|| (distance(&coordinate, &position.coordinate) / seconds * 3.6) as f32);
+                self.fix = Some((point.time, position.coordinate));
+            }
+            None => self.fix = Some((point.time, position.coordinate)),
+        }
+
+        self.fix_speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Coordinate, Position};
+
+    #[test]
+    fn erpm() {
+        let board = Board {
+            pole_pairs: 15,
+            tire_diameter: 11.0 * 0.0254,
+        };
+        assert!((board.speed(15000.0) - 52.66).abs() < 0.01);
+        assert_eq!(board.speed(-15000.0), board.speed(15000.0));
+
+        let mut point = DataPoint {
+            speed: 1.0,
+            erpm: Some(15000.0),
+            ..Default::default()
+        };
+        Speeds::new(SpeedSource::Logged).apply(&mut point);
+        assert_eq!(point.speed, 1.0);
+        Speeds::new(SpeedSource::Erpm(board)).apply(&mut point);
+        assert_eq!(point.speed, board.speed(15000.0));
+    }
+
+    #[test]
+    fn gps() {
+        let mut point = DataPoint {
+            speed: 1.0,
+            ..Default::default()
+        };
+        let mut speeds = Speeds::new(SpeedSource::Gps);
+        speeds.apply(&mut point);
+        assert!(point.speed.is_nan());
+
+        point.position = Some(Position {
+            coordinate: Coordinate {
+                latitude: 1.0,
+                longitude: 1.0,
+            },
+            altitude: None,
+            speed: Some(20.0),
+            accuracy: None,
+        });
+        speeds.apply(&mut point);
+        assert_eq!(point.speed, 20.0);
+    }
+
+    #[test]
+    fn gps_positions() {
+        // a GPS position every second, logged twice a second, moving north 10 m at a time
+        let mut speeds = Speeds::new(SpeedSource::Gps);
+        let mut point = |time: f64, north: f64| {
+            let mut point = DataPoint {
+                time,
+                position: Some(Position {
+                    coordinate: Coordinate {
+                        latitude: north / EARTH_RADIUS * 180.0 / PI as f64,
+                        longitude: 0.0,
+                    },
+                    altitude: None,
+                    speed: None,
+                    accuracy: None,
+                }),
+                ..Default::default()
+            };
+            speeds.apply(&mut point);
+            point.speed
+        };
+
+        assert!(point(0.0, 0.0).is_nan());
+        assert!(point(0.5, 0.0).is_nan());
+        assert!((point(1.0, 10.0) - 36.0).abs() < 1e-3);
+        assert!((point(1.5, 10.0) - 36.0).abs() < 1e-3);
+        assert!((point(2.0, 20.0) - 36.0).abs() < 1e-3);
+
+        // stopped
+        assert!((point(5.0, 20.0) - 36.0).abs() < 1e-3);
+        assert_eq!(point(5.5, 20.0), 0.0);
+    }
+}
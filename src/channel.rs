@@ -0,0 +1,290 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde_derive::Deserialize;
+
+use crate::bail;
+use crate::err::Result;
+use crate::input::{Coordinate, DataPoint, Position};
+
+/// Access to a [`DataPoint`] field as a plain number.
+trait Field {
+    fn get(&self) -> Option<f64>;
+    fn set(&mut self, value: f64);
+}
+
+impl Field for f32 {
+    fn get(&self) -> Option<f64> {
+        (!self.is_nan()).then_some(*self as f64)
+    }
+
+    fn set(&mut self, value: f64) {
+        *self = value as f32;
+    }
+}
+
+impl Field for Option<f32> {
+    fn get(&self) -> Option<f64> {
+        self.map(|value| value as f64)
+    }
+
+    fn set(&mut self, value: f64) {
+        *self = Some(value as f32);
+    }
+}
+
+impl Field for Option<u8> {
+    fn get(&self) -> Option<f64> {
+        self.map(|value| value as f64)
+    }
+
+    fn set(&mut self, value: f64) {
+        *self = Some(value.round().clamp(0.0, u8::MAX as f64) as u8);
+    }
+}
+
+macro_rules! channels {
+    ($($variant:ident => $field:ident, $unit:literal;)*) => {
+        /// A numeric telemetry channel of a [`DataPoint`].
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+        #[serde(try_from = "String")]
+        pub enum Channel {
+            $($variant,)*
+            Latitude,
+            Longitude,
+            Altitude,
+            GpsSpeed,
+            GpsAccuracy,
+        }
+
+        impl Channel {
+            pub const ALL: &'static [Channel] = &[
+                $(Channel::$variant,)*
+                Channel::Latitude,
+                Channel::Longitude,
+                Channel::Altitude,
+                Channel::GpsSpeed,
+                Channel::GpsAccuracy,
+            ];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Channel::$variant => stringify!($field),)*
+                    Channel::Latitude => "latitude",
+                    Channel::Longitude => "longitude",
+                    Channel::Altitude => "altitude",
+                    Channel::GpsSpeed => "gps_speed",
+                    Channel::GpsAccuracy => "gps_accuracy",
+                }
+            }
+
+            /// The unit values of this channel are kept in.
+            pub fn unit(&self) -> &'static str {
+                match self {
+                    $(Channel::$variant => $unit,)*
+                    Channel::Latitude | Channel::Longitude => "°",
+                    Channel::Altitude | Channel::GpsAccuracy => "m",
+                    Channel::GpsSpeed => "km/h",
+                }
+            }
+
+            pub fn get(&self, point: &DataPoint) -> Option<f64> {
+                match self {
+                    $(Channel::$variant => point.$field.get(),)*
+                    _ => {
+                        let position = point.position.as_ref()?;
+                        match self {
+                            Channel::Latitude => Some(position.coordinate.latitude),
+                            Channel::Longitude => Some(position.coordinate.longitude),
+                            Channel::Altitude => position.altitude.get(),
+                            Channel::GpsSpeed => position.speed.get(),
+                            _ => position.accuracy.get(),
+                        }
+                    }
+                }
+            }
+
+            /// Sets the channel's value, a position is added to the point if it lacks one.
+            pub fn set(&self, point: &mut DataPoint, value: f64) {
+                match self {
+                    $(Channel::$variant => point.$field.set(value),)*
+                    _ => {
+                        let position = point.position.get_or_insert(Position {
+                            coordinate: Coordinate {
+                                latitude: f64::NAN,
+                                longitude: f64::NAN,
+                            },
+                            altitude: None,
+                            speed: None,
+                            accuracy: None,
+                        });
+                        match self {
+                            Channel::Latitude => position.coordinate.latitude = value,
+                            Channel::Longitude => position.coordinate.longitude = value,
+                            Channel::Altitude => position.altitude.set(value),
+                            Channel::GpsSpeed => position.speed.set(value),
+                            _ => position.accuracy.set(value),
+                        }
+                    }
+                }
+            }
+        }
+    };
+}
+
+channels! {
+    Speed => speed, "km/h";
+    DutyCycle => duty_cycle, "%";
+    MotorCurrent => motor_current, "A";
+    FieldWeakening => field_weakening, "A";
+    CurrentRequested => current_requested, "A";
+    CurrentBooster => current_booster, "A";
+    Erpm => erpm, "rpm";
+    TempMotor => temp_motor, "°C";
+    TempMosfet => temp_mosfet, "°C";
+    TempBattery => temp_battery, "°C";
+    TempBms => temp_bms, "°C";
+    BattVoltage => batt_voltage, "V";
+    BattCurrent => batt_current, "A";
+    BatteryPercent => battery_percent, "%";
+    StateCode => state_code, "";
+    FaultMotor => fault_motor, "";
+    FaultBms => fault_bms, "";
+    Adc1 => adc1, "V";
+    Adc2 => adc2, "V";
+    Pitch => pitch, "°";
+    TruePitch => true_pitch, "°";
+    Roll => roll, "°";
+    Setpoint => setpoint, "°";
+    SetpointAtr => setpoint_atr, "°";
+    SetpointCarve => setpoint_carve, "°";
+    SetpointTorqueTilt => setpoint_torque_tilt, "°";
+    SetpointBrakeTilt => setpoint_brake_tilt, "°";
+    SetpointRemote => setpoint_remote, "°";
+    Distance => distance, "km";
+    AmpHours => amp_hours, "Ah";
+    AmpHoursCharged => amp_hours_charged, "Ah";
+    WattHours => watt_hours, "Wh";
+    WattHoursCharged => watt_hours_charged, "Wh";
+    Power => power, "W";
+    Efficiency => efficiency, "Wh/km";
+    Acceleration => acceleration, "m/s²";
+}
+
+/// Conversions into the units channels are kept in: `(from, to, factor, offset)`.
+const CONVERSIONS: &[(&str, &str, f64, f64)] = &[
+    ("kph", "km/h", 1.0, 0.0),
+    ("mph", "km/h", 1.609344, 0.0),
+    ("m/s", "km/h", 3.6, 0.0),
+    ("mi", "km", 1.609344, 0.0),
+    ("m", "km", 0.001, 0.0),
+    ("ft", "m", 0.3048, 0.0),
+    ("km", "m", 1000.0, 0.0),
+    ("C", "°C", 1.0, 0.0),
+    ("F", "°C", 5.0 / 9.0, -32.0 * 5.0 / 9.0),
+    ("°F", "°C", 5.0 / 9.0, -32.0 * 5.0 / 9.0),
+    ("deg", "°", 1.0, 0.0),
+    ("rad", "°", 180.0 / std::f64::consts::PI, 0.0),
+    ("fraction", "%", 100.0, 0.0),
+    ("mA", "A", 0.001, 0.0),
+    ("mV", "V", 0.001, 0.0),
+    ("mAh", "Ah", 0.001, 0.0),
+    ("kWh", "Wh", 1000.0, 0.0),
+    ("kW", "W", 1000.0, 0.0),
+];
+
+impl Channel {
+    /// Whether the channel holds codes rather than measurements, so can't be interpolated.
+    pub fn is_discrete(&self) -> bool {
+        matches!(
+            self,
+            Channel::StateCode | Channel::FaultMotor | Channel::FaultBms
+        )
+    }
+
+    /// Converts a value in `unit` to this channel's unit.
+    pub fn convert(&self, value: f64, unit: &str) -> Result<f64> {
+        if unit == self.unit() {
+            return Ok(value);
+        }
+
+        match CONVERSIONS
+            .iter()
+            .find(|(from, to, ..)| *from == unit && *to == self.unit())
+        {
+            Some((_, _, factor, offset)) => Ok(value * factor + offset),
+            None => bail!(
+                "cannot convert {} to {} for channel {}",
+                unit,
+                self.unit(),
+                self.name()
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Channel::ALL
+            .iter()
+            .find(|channel| channel.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown channel {}", s))
+    }
+}
+
+impl TryFrom<String> for Channel {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_set() {
+        let mut point = DataPoint::default();
+        assert_eq!(Channel::Speed.get(&point), None);
+        assert_eq!(Channel::Pitch.get(&point), None);
+        assert_eq!(Channel::Latitude.get(&point), None);
+
+        Channel::Speed.set(&mut point, 12.5);
+        Channel::Pitch.set(&mut point, -2.0);
+        Channel::FaultMotor.set(&mut point, 3.0);
+        Channel::Latitude.set(&mut point, -1.25);
+        assert_eq!(point.speed, 12.5);
+        assert_eq!(point.pitch, Some(-2.0));
+        assert_eq!(point.fault_motor, Some(3));
+        assert_eq!(Channel::Latitude.get(&point), Some(-1.25));
+        assert_eq!(Channel::Longitude.get(&point).map(f64::is_nan), Some(true));
+    }
+
+    #[test]
+    fn names() {
+        for channel in Channel::ALL {
+            assert_eq!(channel.name().parse(), Ok(*channel));
+        }
+        assert!("nope".parse::<Channel>().is_err());
+    }
+
+    #[test]
+    fn convert() {
+        assert_eq!(Channel::Speed.convert(10.0, "km/h").unwrap(), 10.0);
+        assert_eq!(Channel::Speed.convert(10.0, "m/s").unwrap(), 36.0);
+        assert!((Channel::TempMotor.convert(212.0, "F").unwrap() - 100.0).abs() < 1e-9);
+        assert_eq!(Channel::Distance.convert(500.0, "m").unwrap(), 0.5);
+        assert!(Channel::Speed.convert(1.0, "V").is_err());
+    }
+}
@@ -3,7 +3,14 @@ use std::{env, process};
 use lexopt::Parser;
 
 use crate::bail;
+use crate::battery::Chemistry;
+use crate::board::{Board, SpeedSource};
+use crate::channel::Channel;
 use crate::err::Result;
+use crate::filter::{self, Filter};
+use crate::input::{self, LogFormat, ZipEntry};
+use crate::resample::{Gaps, Interpolation};
+use crate::trim::Bound;
 
 fn print_help() {
     println!(
@@ -16,25 +23,81 @@ fn print_help() {
 Project home page: {crate_homepage}
 
 USAGE:
-    {bin} [OPTIONS] <INPUT_FILE>
+    {bin} [OPTIONS] <INPUT_FILE>...
 
 INPUT_FILE:
-    This should be one of:
+    Each file should be one of:
         - Float Control CSV or ZIP
         - Floaty JSON
+        - VESC Tool realtime log CSV
+        - any other CSV, when given a --mapping file
+    The format is detected from the file's contents, see --format to set it explicitly.
+    Several files are joined into one video, in order of their start times.
+    Use `-` to read from stdin. Gzip compressed files are decompressed automatically.
 
 REQUIRED FLAGS:
-    -c, --cell-count <COUNT>         Number of cells in the battery pack
     -f, --font <FONT>                Path to the font file (TTF) to use for rendering text
 
 OPTIONAL FLAGS:
+    -b, --battery <CELL>             Cells the pack is built from, for its state of charge, one of: p42a,
+                                     p45b, 50s, lfp [default: p42a]
+    -c, --cell-count <COUNT>         Number of cells in series in the battery pack, for the voltage per cell
+                                     and state of charge [default: guessed from the pack's voltage at the
+                                     start of the ride, if only one count fits]
+    -d, --tire-diameter <INCHES>     Diameter of the tire, for --speed-source erpm
+        --end <TIME>                 Where in the ride to end the video, in seconds (1800), minutes and
+                                     seconds (30:00), or as a date and time (2024-09-16T10:06:49Z)
+    -F, --format <FORMAT>            Input format, one of: zip, float-control, floaty,
+                                     vesc-tool, mapped [default: detected]
+        --list-formats               List the input formats and the channels each provides
+        --list-sync-markers          List the sync gestures found in the ride, see --sync-marker
+        --filter <CHANNEL=FILTER>    Smooth a channel with a filter, one of: ema:<WEIGHT> (an exponential
+                                     moving average, weighting new values from 0 to 1), average:<SAMPLES>
+                                     or median:<SAMPLES>, e.g. --filter motor_current=ema:0.3. Can be
+                                     given more than once
+        --filter-file <FILE>         Path to a JSON file of filters by channel, e.g.
+                                     {{"motor_current": "ema:0.3"}}, see test_data/filters.json
     -g, --max-gap-seconds <SECONDS>  Maximum gap between data points (in seconds) [default: 2.0]
+        --gaps <MODE>                What's done with gaps longer than --max-gap-seconds, one of: keep (at
+                                     their full length), cut, clamp (to --max-gap-seconds), pause (clamp,
+                                     showing that the signal was lost). Only keep can be used with
+                                     --offset, --sync-video or --sync-marker [default: clamp, or keep
+                                     with --offset, --sync-video or --sync-marker]
+    -i, --interpolation <MODE>       How values are filled in between data points, one of: none, linear,
+                                     cubic [default: linear]
+        --keep-outliers              Keep impossible values and single sample spikes, rather than replacing
+                                     them with values from the samples either side [default: false]
+    -l, --lenient                    Skip rows that can't be parsed instead of failing, warning about each
+                                     as it's found, and print the totals once the input has been read
+                                     [default: false]
+    -m, --mapping <MAPPING>          Path to a JSON file describing how to read the columns of a CSV in an
+                                     unsupported format, see test_data/mapping.json for an example
     -o, --output <OUTPUT>            Output file name [default: $input_file_name.mov]
+        --offset <SECONDS>           Seconds into the ride the video starts at, negative to start it before
+                                     the ride, or to shift it by with --sync-video [default: first sample]
+    -p, --pole-pairs <COUNT>         Number of pole pairs in the motor, for --speed-source erpm
+        --parallel <COUNT>           Number of cells in parallel in the battery pack [default: 1]
     -r, --rate <FRAME_RATE>          Frame rate of the output video [default: 30]
     -s, --scale <SCALE>              Scale factor for the output video [default: 1.0]
+    -S, --speed-source <SOURCE>      Where speed is taken from, one of: logged, erpm (which needs
+                                     --pole-pairs and --tire-diameter), gps (worked out from the GPS
+                                     positions if the log has no GPS speed) [default: logged]
+        --start <TIME>               Where in the ride to start the video, see --end
+        --sync-marker                Start the video at the first sync gesture found in the ride, shifted
+                                     by --offset. Make one while stopped, by quickly tapping one half of
+                                     the footpad twice, or sharply rolling the board and back
+        --sync-video <VIDEO>         Line the video up with a camera's clip, using its creation time and
+                                     duration read with ffprobe and the ride's start time. Floaty JSON,
+                                     Float Control ZIPs and CSVs mapped with a date and time have a start
+                                     time, other logs don't. Some cameras record local time as UTC, as do
+                                     Float Control ZIPs, correct for that with --offset
     -t, --title-font <TITLE_FONT>    Path to the font file (TTF) to use for rendering titles [default: FONT]
     -T, --transparent                Encode with a transparent background - note that due to encoding
                                      formats, enabling this significantly increases file size [default: false]
+        --trim-idle                  Leave out the time the board sat idle before and after the ride, going
+                                     by its state or, if the log has none, its speed [default: false]
+    -z, --zip-entry <ENTRY>          Which CSV to read from a Float Control ZIP: a file name, an index, or
+                                     `all` to join every CSV in the archive in time order [default: first CSV]
 
 
     -h, --help                       Print help information
@@ -45,6 +108,16 @@ EXAMPLES:
     {bin} --scale 1.2      path/to/float-control.csv
     {bin} --rate 60        path/to/floaty.json
     {bin} --output vid.mov path/to/floaty.json
+    {bin} --zip-entry all  path/to/float-control.zip
+    {bin} --mapping map.json path/to/other.csv
+    {bin}                  path/to/part-1.json path/to/part-2.json
+    {bin} --speed-source erpm --pole-pairs 15 --tire-diameter 11 path/to/float-control.csv
+    {bin} --start 2:00 --end 14:30 path/to/float-control.csv
+    {bin} --trim-idle      path/to/floaty.json
+    {bin} --sync-video GX010042.MP4 --offset -3600 path/to/floaty.json
+    {bin} --sync-marker --offset -5 path/to/float-control.csv
+    {bin} --filter duty_cycle=median:5 --filter-file filters.json path/to/floaty.json
+    zcat backup.tar.gz | tar -xO ride.csv | {bin} -
 
     "#,
             bin = env!("CARGO_BIN_NAME"),
@@ -60,51 +133,149 @@ EXAMPLES:
     );
 }
 
+fn print_formats() {
+    for format in input::FORMATS {
+        let channels = format.channels();
+        println!("{:<16}{}", format.name(), format.description());
+        if channels.len() == Channel::ALL.len() {
+            println!("{:<16}channels: any", "");
+        } else {
+            println!(
+                "{:<16}channels: {}",
+                "",
+                channels
+                    .iter()
+                    .map(Channel::name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Args {
-    pub input: String,
+    pub inputs: Vec<String>,
+    pub format: Option<&'static dyn LogFormat>,
+    pub mapping: Option<String>,
     pub output: String,
+    pub offset: Option<f64>,
+    pub sync_video: Option<String>,
+    pub sync_marker: bool,
+    pub list_sync_markers: bool,
     pub max_gap_seconds: f32,
-    pub cell_count: u8,
+    pub gaps: Gaps,
+    pub interpolation: Interpolation,
+    pub filters: Vec<(Channel, Filter)>,
+    pub cell_count: Option<u8>,
+    pub chemistry: Chemistry,
+    pub parallel_count: u8,
     pub rate: f32,
     pub scale: f32,
     pub font: String,
     pub title_font: String,
     pub transparent_bg: bool,
+    pub speed_source: SpeedSource,
+    pub start: Option<Bound>,
+    pub end: Option<Bound>,
+    pub trim_idle: bool,
+    pub zip_entry: ZipEntry,
+    pub lenient: bool,
+    pub keep_outliers: bool,
 }
 
 impl Args {
     pub fn parse() -> Result<Args> {
         use lexopt::prelude::*;
 
-        let mut input = None;
+        let mut inputs = vec![];
+        let mut format = None;
+        let mut mapping = None;
 
         let mut max_gap_seconds = None;
+        let mut gaps = None;
+        let mut interpolation = None;
+        let mut filter_file = None;
+        let mut filters = vec![];
         let mut cell_count = None;
         let mut rate = None;
         let mut output = None;
+        let mut offset = None;
+        let mut sync_video = None;
+        let mut sync_marker = false;
+        let mut list_sync_markers = false;
         let mut font = None;
         let mut title_font = None;
         let mut scale = None;
         let mut transparent_bg = false;
+        let mut start = None;
+        let mut end = None;
+        let mut trim_idle = false;
+        let mut speed_source = None;
+        let mut pole_pairs = None;
+        let mut chemistry = None;
+        let mut parallel_count = None;
+        let mut tire_diameter: Option<f32> = None;
+        let mut zip_entry = None;
+        let mut lenient = false;
+        let mut keep_outliers = false;
 
         let mut parser = Parser::from_env();
         while let Some(arg) = parser.next()? {
             match arg {
                 Short('s') | Long("scale") => scale = Some(parser.value()?.string()?.parse()?),
+                Short('F') | Long("format") => {
+                    format = Some(input::find_format(&parser.value()?.string()?)?)
+                }
+                Long("list-formats") => {
+                    print_formats();
+                    process::exit(0);
+                }
+                Short('m') | Long("mapping") => mapping = Some(parser.value()?.string()?),
                 Short('f') | Long("font") => font = Some(parser.value()?.string()?.into()),
                 Short('T') | Long("transparent") => transparent_bg = true,
+                Long("start") => start = Some(parser.value()?.string()?.parse()?),
+                Long("offset") => offset = Some(parser.value()?.string()?.parse()?),
+                Long("sync-video") => sync_video = Some(parser.value()?.string()?),
+                Long("sync-marker") => sync_marker = true,
+                Long("list-sync-markers") => list_sync_markers = true,
+                Long("end") => end = Some(parser.value()?.string()?.parse()?),
+                Long("trim-idle") => trim_idle = true,
+                Short('S') | Long("speed-source") => speed_source = Some(parser.value()?.string()?),
+                Short('p') | Long("pole-pairs") => {
+                    pole_pairs = Some(parser.value()?.string()?.parse()?)
+                }
+                Short('d') | Long("tire-diameter") => {
+                    tire_diameter = Some(parser.value()?.string()?.parse()?)
+                }
+                Long("keep-outliers") => keep_outliers = true,
+                Short('l') | Long("lenient") => lenient = true,
                 Short('t') | Long("title-font") => {
                     title_font = Some(parser.value()?.string()?.into())
                 }
                 Short('r') | Long("rate") => rate = Some(parser.value()?.string()?.parse()?),
                 Short('o') | Long("output") => output = Some(parser.value()?.string()?.into()),
+                Short('b') | Long("battery") => {
+                    chemistry = Some(parser.value()?.string()?.parse()?)
+                }
+                Long("parallel") => parallel_count = Some(parser.value()?.string()?.parse()?),
                 Short('c') | Long("cell-count") => {
                     cell_count = Some(parser.value()?.string()?.parse()?)
                 }
                 Short('g') | Long("max-gap-seconds") => {
                     max_gap_seconds = Some(parser.value()?.string()?.parse()?)
                 }
+                Long("gaps") => gaps = Some(parser.value()?.string()?.parse()?),
+                Long("filter") => {
+                    filters.push(filter::parse_channel_filter(&parser.value()?.string()?)?)
+                }
+                Long("filter-file") => filter_file = Some(parser.value()?.string()?),
+                Short('i') | Long("interpolation") => {
+                    interpolation = Some(parser.value()?.string()?.parse()?)
+                }
+                Short('z') | Long("zip-entry") => {
+                    zip_entry = Some(parser.value()?.string()?.parse()?)
+                }
                 Short('h') | Long("help") => {
                     print_help();
                     process::exit(0);
@@ -117,40 +288,105 @@ impl Args {
                     );
                     process::exit(0);
                 }
-                Value(val) if input.is_none() => {
-                    input = Some(val.string()?.into());
-                }
-                Short(_) | Long(_) | Value(_) => {
+                Value(val) => inputs.push(val.string()?),
+                Short(_) | Long(_) => {
                     print_help();
                     process::exit(1);
                 }
             }
         }
 
-        if input.is_none() {
+        if inputs.is_empty() {
             bail!("no input file specified");
         }
 
-        if cell_count.is_none() {
-            print_help();
-            bail!("cell count is required");
+        let speed_source = match speed_source.as_deref() {
+            None | Some("logged") => SpeedSource::Logged,
+            Some("gps") => SpeedSource::Gps,
+            Some("erpm") => match (pole_pairs, tire_diameter) {
+                (Some(0), _) => bail!("the motor needs at least one pole pair"),
+                (_, Some(tire_diameter)) if tire_diameter.is_nan() || tire_diameter <= 0.0 => {
+                    bail!("the tire diameter must be more than zero")
+                }
+                (Some(pole_pairs), Some(tire_diameter)) => SpeedSource::Erpm(Board {
+                    pole_pairs,
+                    tire_diameter: tire_diameter * 0.0254,
+                }),
+                _ => bail!("speed from erpm needs --pole-pairs and --tire-diameter"),
+            },
+            Some(source) => bail!(
+                "unknown speed source {}, expected one of: logged, erpm, gps",
+                source
+            ),
+        };
+
+        if cell_count == Some(0) {
+            bail!("the pack needs at least one cell in series");
+        }
+        if parallel_count == Some(0) {
+            bail!("the pack needs at least one cell in parallel");
         }
 
-        if font.is_none() {
-            print_help();
-            bail!("font is required");
+        // filters given on the command line replace those from the file
+        if let Some(filter_file) = filter_file {
+            let mut from_file = filter::load(filter_file)?;
+            from_file.retain(|(channel, _)| filters.iter().all(|(c, _)| c != channel));
+            filters.splice(0..0, from_file);
         }
 
+        if sync_marker && sync_video.is_some() {
+            bail!("--sync-marker and --sync-video can't be used together");
+        }
+
+        // shortening gaps would put the rest of the render out of time with the footage
+        let syncing = offset.is_some() || sync_video.is_some() || sync_marker;
+        let gaps = match gaps {
+            Some(gaps) if syncing && gaps != Gaps::Keep => {
+                bail!("only --gaps keep can be used with --offset, --sync-video or --sync-marker")
+            }
+            Some(gaps) => gaps,
+            None if syncing => Gaps::Keep,
+            None => Gaps::Clamp,
+        };
+
+        // nothing is rendered when listing sync markers
+        let font = match font {
+            Some(font) => font,
+            None if list_sync_markers => String::new(),
+            None => {
+                print_help();
+                bail!("font is required");
+            }
+        };
+
         Ok(Args {
-            input: input.unwrap(),
+            inputs,
+            format,
+            mapping,
             output: output.unwrap_or(String::from("output.mov")),
             max_gap_seconds: max_gap_seconds.unwrap_or(2.0),
-            cell_count: cell_count.unwrap(),
-            title_font: title_font.unwrap_or_else(|| font.clone().unwrap()),
-            font: font.unwrap(),
+            gaps,
+            offset,
+            sync_video,
+            sync_marker,
+            list_sync_markers,
+            interpolation: interpolation.unwrap_or(Interpolation::Linear),
+            filters,
+            cell_count,
+            chemistry: chemistry.unwrap_or(Chemistry::P42a),
+            parallel_count: parallel_count.unwrap_or(1),
+            title_font: title_font.unwrap_or_else(|| font.clone()),
+            font,
             rate: rate.unwrap_or(30.0),
             scale: scale.unwrap_or(1.0),
             transparent_bg,
+            start,
+            end,
+            trim_idle,
+            speed_source,
+            zip_entry: zip_entry.unwrap_or_default(),
+            lenient,
+            keep_outliers,
         })
     }
 }
@@ -0,0 +1,171 @@
+use crate::err::Result;
+use crate::input::DataPoint;
+
+/// Samples further apart than this (in seconds) are taken to be either side of a break in logging,
+/// which isn't integrated over.
+const MAX_STEP: f64 = 5.0;
+
+/// Distance (in km) to ride before efficiency is worked out, as it's meaningless until then.
+const MIN_EFFICIENCY_DISTANCE: f64 = 0.1;
+
+/// Fills in channels which can be worked out from others.
+///
+/// Distance and energy are integrated from speed and power. They, and power, are only filled in
+/// where the log didn't record them, while efficiency and acceleration are always worked out.
+pub struct Derived<I> {
+    samples: I,
+    previous: Option<DataPoint>,
+    /// Running totals since the start of the ride, in km and Wh.
+    distance: f64,
+    watt_hours: f64,
+    watt_hours_charged: f64,
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Derived<I> {
+    pub fn new(samples: I) -> Self {
+        Derived {
+            samples,
+            previous: None,
+            distance: 0.0,
+            watt_hours: 0.0,
+            watt_hours_charged: 0.0,
+        }
+    }
+
+    fn derive(&mut self, point: &mut DataPoint) {
+        let power = point.batt_voltage as f64 * point.batt_current as f64;
+        point.power = point.power.or((!power.is_nan()).then_some(power as f32));
+
+        if let Some(previous) = self
+            .previous
+            .as_ref()
+            .filter(|previous| point.time > previous.time && point.time - previous.time <= MAX_STEP)
+        {
+            let seconds = point.time - previous.time;
+            let hours = seconds / 3600.0;
+
+            // trapezoidal integration, skipping steps where either end is missing
+            let speed = (previous.speed as f64 + point.speed as f64) / 2.0;
+            if !speed.is_nan() {
+                self.distance += speed.abs() * hours;
+            }
+
+            if let (Some(p1), Some(p2)) = (previous.power, point.power) {
+                let energy = (p1 as f64 + p2 as f64) / 2.0 * hours;
+                if energy > 0.0 {
+                    self.watt_hours += energy;
+                } else {
+                    self.watt_hours_charged -= energy;
+                }
+            }
+
+            let acceleration = (point.speed - previous.speed) as f64 / 3.6 / seconds;
+            point.acceleration = (!acceleration.is_nan()).then_some(acceleration as f32);
+        }
+
+        point.distance = point.distance.or(Some(self.distance as f32));
+        point.watt_hours = point.watt_hours.or(Some(self.watt_hours as f32));
+        point.watt_hours_charged = point
+            .watt_hours_charged
+            .or(Some(self.watt_hours_charged as f32));
+
+        point.efficiency = match (point.distance, point.watt_hours, point.watt_hours_charged) {
+            (Some(distance), Some(used), Some(charged))
+                if distance as f64 >= MIN_EFFICIENCY_DISTANCE =>
+            {
+                Some((used - charged) / distance)
+            }
+            _ => None,
+        };
+    }
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Iterator for Derived<I> {
+    type Item = Result<DataPoint>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut point = match self.samples.next()? {
+            Ok(point) => point,
+            Err(e) => return Some(Err(e)),
+        };
+
+        self.derive(&mut point);
+        self.previous = Some(point.clone());
+        Some(Ok(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn derive(points: &[(f64, f32, f32, f32)]) -> Vec<DataPoint> {
+        let samples = points
+            .iter()
+            .map(|(time, speed, voltage, current)| {
+                Ok(DataPoint {
+                    time: *time,
+                    speed: *speed,
+                    batt_voltage: *voltage,
+                    batt_current: *current,
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Derived::new(samples.into_iter())
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn integrate() {
+        // 36 km/h for 60 seconds, drawing 1 kW then regenerating 0.5 kW
+        let samples = (0..=60)
+            .map(|t| (t as f64, 36.0, 100.0, if t <= 30 { 10.0 } else { -5.0 }))
+            .collect::<Vec<_>>();
+        let points = derive(&samples);
+
+        let last = &points[60];
+        assert_eq!(points[0].power, Some(1000.0));
+        assert_eq!(last.power, Some(-500.0));
+        assert!((last.distance.unwrap() - 0.6).abs() < 1e-6);
+        assert!((last.watt_hours.unwrap() - 30250.0 / 3600.0).abs() < 1e-4);
+        assert!((last.watt_hours_charged.unwrap() - 14500.0 / 3600.0).abs() < 1e-4);
+
+        let efficiency =
+            (last.watt_hours.unwrap() - last.watt_hours_charged.unwrap()) / last.distance.unwrap();
+        assert_eq!(last.efficiency, Some(efficiency));
+        assert_eq!(points[0].efficiency, None);
+    }
+
+    #[test]
+    fn acceleration() {
+        let points = derive(&[(0.0, 0.0, 80.0, 1.0), (0.5, 9.0, 80.0, 1.0)]);
+        assert_eq!(points[0].acceleration, None);
+        assert_eq!(points[1].acceleration, Some(5.0));
+    }
+
+    #[test]
+    fn gaps() {
+        // time spent not logging isn't integrated over
+        let points = derive(&[(0.0, 36.0, 80.0, 1.0), (60.0, 36.0, 80.0, 1.0)]);
+        assert_eq!(points[1].distance, Some(0.0));
+        assert_eq!(points[1].acceleration, None);
+    }
+
+    #[test]
+    fn logged() {
+        let sample = DataPoint {
+            speed: 36.0,
+            distance: Some(12.0),
+            ..Default::default()
+        };
+        let points = Derived::new([Ok(sample)].into_iter())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(points[0].distance, Some(12.0));
+        assert_eq!(points[0].watt_hours, Some(0.0));
+        assert_eq!(points[0].power, None);
+    }
+}
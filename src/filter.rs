@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::bail;
+use crate::channel::Channel;
+use crate::err::Result;
+use crate::input::DataPoint;
+
+/// A smoothing filter for a channel.
+///
+/// Averages and medians are taken over a window centred on each sample, so they don't lag behind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Exponential moving average, weighting each new sample by `alpha` (from 0 to 1).
+    Ema(f64),
+    /// Mean of this many samples.
+    Average(usize),
+    /// Median of this many samples, which drops spikes without softening real changes.
+    Median(usize),
+}
+
+impl Filter {
+    /// How many samples before the current one the filter looks at.
+    fn history(&self) -> usize {
+        match self {
+            Filter::Ema(_) => 0,
+            Filter::Average(window) | Filter::Median(window) => window / 2,
+        }
+    }
+
+    /// How many samples after the current one the filter looks at. Even windows take one more
+    /// sample from before than after.
+    fn lookahead(&self) -> usize {
+        match self {
+            Filter::Ema(_) => 0,
+            Filter::Average(window) | Filter::Median(window) => (window - 1) / 2,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (kind, parameter) = s.split_once(':').unwrap_or((s, ""));
+        let filter = match kind {
+            "ema" => match parameter.parse() {
+                Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => Filter::Ema(alpha),
+                _ => {
+                    return Err(format!(
+                        "ema needs a weight from 0 to 1, e.g. ema:0.3, not {}",
+                        s
+                    ))
+                }
+            },
+            "average" | "median" => match parameter.parse() {
+                Ok(window) if window > 0 && kind == "average" => Filter::Average(window),
+                Ok(window) if window > 0 => Filter::Median(window),
+                _ => {
+                    return Err(format!(
+                        "{} needs a number of samples, e.g. {}:5, not {}",
+                        kind, kind, s
+                    ))
+                }
+            },
+            _ => {
+                return Err(format!(
+                    "unknown filter {}, expected one of: ema, average, median",
+                    s
+                ))
+            }
+        };
+
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Ema(alpha) => write!(f, "ema:{}", alpha),
+            Filter::Average(window) => write!(f, "average:{}", window),
+            Filter::Median(window) => write!(f, "median:{}", window),
+        }
+    }
+}
+
+/// Parses a `CHANNEL=FILTER` pair, e.g. `motor_current=ema:0.3`.
+pub fn parse_channel_filter(s: &str) -> Result<(Channel, Filter)> {
+    let Some((channel, filter)) = s.split_once('=') else {
+        bail!(
+            "expected CHANNEL=FILTER, e.g. motor_current=ema:0.3, not {}",
+            s
+        );
+    };
+
+    check(channel.parse()?, filter.parse()?)
+}
+
+fn check(channel: Channel, filter: Filter) -> Result<(Channel, Filter)> {
+    if channel.is_discrete() {
+        bail!("{} holds codes, which can't be smoothed", channel);
+    }
+
+    Ok((channel, filter))
+}
+
+/// Reads the filters for each channel from a JSON file, e.g. `{"motor_current": "ema:0.3"}`.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<(Channel, Filter)>> {
+    let path = path.as_ref();
+    let filters: BTreeMap<String, String> =
+        serde_json::from_reader(BufReader::new(File::open(path)?))
+            .map_err(|e| format!("invalid filter file {}: {}", path.display(), e))?;
+
+    filters
+        .iter()
+        .map(|(channel, filter)| check(channel.parse()?, filter.parse()?))
+        .collect()
+}
+
+/// Runs the ride's channels through their filters.
+pub struct Smoothed<I> {
+    samples: I,
+    filters: Vec<(Channel, Filter)>,
+    /// Each exponential moving average's running value.
+    averages: Vec<Option<f64>>,
+    history: usize,
+    lookahead: usize,
+    /// The unfiltered samples around the next to be sent, which is at `current`.
+    window: VecDeque<DataPoint>,
+    current: usize,
+    /// Reading stops at an error, which is passed on once the samples before it are sent.
+    error: Option<Box<dyn std::error::Error>>,
+    exhausted: bool,
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Smoothed<I> {
+    pub fn new(samples: I, filters: Vec<(Channel, Filter)>) -> Self {
+        Smoothed {
+            samples,
+            averages: vec![None; filters.len()],
+            history: filters.iter().map(|(_, f)| f.history()).max().unwrap_or(0),
+            lookahead: filters
+                .iter()
+                .map(|(_, f)| f.lookahead())
+                .max()
+                .unwrap_or(0),
+            filters,
+            window: VecDeque::new(),
+            current: 0,
+            error: None,
+            exhausted: false,
+        }
+    }
+
+    fn filter(&mut self) -> DataPoint {
+        let mut point = self.window[self.current].clone();
+        for (i, (channel, filter)) in self.filters.iter().enumerate() {
+            let Some(value) = channel.get(&point) else {
+                continue;
+            };
+
+            let window = |filter: &Filter| {
+                let start = self.current.saturating_sub(filter.history());
+                let end = (self.current + filter.lookahead() + 1).min(self.window.len());
+                self.window
+                    .range(start..end)
+                    .filter_map(|point| channel.get(point))
+                    .collect::<Vec<_>>()
+            };
+
+            let value = match filter {
+                Filter::Ema(alpha) => {
+                    let average = self.averages[i].map_or(value, |a| a + alpha * (value - a));
+                    self.averages[i] = Some(average);
+                    average
+                }
+                Filter::Average(_) => {
+                    let values = window(filter);
+                    values.iter().sum::<f64>() / values.len() as f64
+                }
+                Filter::Median(_) => {
+                    let mut values = window(filter);
+                    values.sort_by(f64::total_cmp);
+                    let mid = values.len() / 2;
+                    if values.len() % 2 == 0 {
+                        (values[mid - 1] + values[mid]) / 2.0
+                    } else {
+                        values[mid]
+                    }
+                }
+            };
+            channel.set(&mut point, value);
+        }
+
+        point
+    }
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Iterator for Smoothed<I> {
+    type Item = Result<DataPoint>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.exhausted && self.window.len() <= self.current + self.lookahead {
+            match self.samples.next() {
+                Some(Ok(point)) => self.window.push_back(point),
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    self.exhausted = true;
+                }
+                None => self.exhausted = true,
+            }
+        }
+
+        if self.current >= self.window.len() {
+            return self.error.take().map(Err);
+        }
+
+        let point = self.filter();
+        self.current += 1;
+        if self.current > self.history {
+            self.window.pop_front();
+            self.current -= 1;
+        }
+
+        Some(Ok(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smooth(speeds: &[f32], filter: &str) -> Vec<f32> {
+        let samples = speeds
+            .iter()
+            .map(|speed| {
+                Ok(DataPoint {
+                    speed: *speed,
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        let filters = vec![parse_channel_filter(&format!("speed={}", filter)).unwrap()];
+
+        Smoothed::new(samples.into_iter(), filters)
+            .map(|point| point.unwrap().speed)
+            .collect()
+    }
+
+    #[test]
+    fn filters() {
+        let speeds = [10.0, 10.0, 40.0, 10.0, 10.0, 20.0];
+        assert_eq!(
+            smooth(&speeds, "median:3"),
+            vec![10.0, 10.0, 10.0, 10.0, 10.0, 15.0]
+        );
+        assert_eq!(
+            smooth(&speeds, "average:3"),
+            vec![10.0, 20.0, 20.0, 20.0, 13.333333, 15.0]
+        );
+        assert_eq!(
+            smooth(&speeds, "ema:0.5"),
+            vec![10.0, 10.0, 25.0, 17.5, 13.75, 16.875]
+        );
+        assert_eq!(smooth(&speeds, "average:1"), speeds);
+
+        // even windows lean back in time
+        assert_eq!(
+            smooth(&speeds, "average:2"),
+            vec![10.0, 10.0, 25.0, 25.0, 10.0, 15.0]
+        );
+    }
+
+    #[test]
+    fn missing_values() {
+        assert!(smooth(&[10.0, f32::NAN, 20.0], "average:3")[1].is_nan());
+        assert_eq!(smooth(&[10.0, f32::NAN, 20.0], "average:3")[2], 20.0);
+        assert_eq!(smooth(&[10.0, f32::NAN, 20.0], "ema:0.5")[2], 15.0);
+    }
+
+    #[test]
+    fn errors() {
+        let samples: Vec<Result<DataPoint>> = vec![
+            Ok(DataPoint::default()),
+            Ok(DataPoint::default()),
+            Err("bad row".into()),
+        ];
+        let filters = vec![(Channel::Speed, Filter::Median(5))];
+        let points = Smoothed::new(samples.into_iter(), filters).collect::<Vec<_>>();
+        assert_eq!(points.len(), 3);
+        assert!(points[1].is_ok());
+        assert!(points[2].is_err());
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("ema:0.3".parse(), Ok(Filter::Ema(0.3)));
+        assert_eq!("median:5".parse(), Ok(Filter::Median(5)));
+        assert!("ema:2".parse::<Filter>().is_err());
+        assert!("average".parse::<Filter>().is_err());
+        assert!("kalman:1".parse::<Filter>().is_err());
+        assert!(parse_channel_filter("state_code=median:3").is_err());
+        assert!(parse_channel_filter("speed").is_err());
+
+        let filters = load("test_data/filters.json").unwrap();
+        assert_eq!(
+            filters,
+            vec![
+                (Channel::DutyCycle, Filter::Median(5)),
+                (Channel::MotorCurrent, Filter::Ema(0.3)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::io::{self, Read, Seek};
+use std::str::FromStr;
+
+use super::float_control::{self, parse_float_control};
+use super::{unix_millis, LogFormat, Options, Sink};
+use crate::bail;
+use crate::channel::Channel;
+use crate::err::Result;
+
+/// Which entry to read from a Float Control ZIP archive.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ZipEntry {
+    /// The first CSV in the archive.
+    #[default]
+    First,
+    /// The entry at this position in the archive.
+    Index(usize),
+    /// The entry with this name.
+    Name(String),
+    /// Every CSV in the archive, joined in time order.
+    All,
+}
+
+impl FromStr for ZipEntry {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "all" => ZipEntry::All,
+            _ => match s.parse() {
+                Ok(index) => ZipEntry::Index(index),
+                Err(_) => ZipEntry::Name(s.to_string()),
+            },
+        })
+    }
+}
+
+/// Whether a ZIP entry looks like a ride CSV, skipping the resource forks macOS adds.
+fn is_ride_csv(name: &str) -> bool {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    name.to_lowercase().ends_with(".csv")
+        && !name.starts_with("__MACOSX/")
+        && !file_name.starts_with("._")
+}
+
+/// When a ZIP entry was last modified, for ordering the entries.
+///
+/// The app writes the CSV as the ride is recorded, so this is roughly when the ride ended. It has
+/// no timezone and is taken as UTC, though the phone will usually have written its local time.
+fn zip_entry_modified<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    index: usize,
+) -> Result<Option<i64>> {
+    let file = archive.by_index_raw(index)?;
+    Ok(file.last_modified().map(|dt| {
+        unix_millis(
+            dt.year() as i64,
+            dt.month() as u32,
+            dt.day() as u32,
+            dt.hour() as u32,
+            dt.minute() as u32,
+            dt.second() as f64,
+        )
+    }))
+}
+
+/// Estimates when the ride in a ZIP entry started, from when it was `modified` less how long the
+/// ride was, which is found by reading through the entry.
+///
+/// Like the modification time, this may be in local time rather than UTC, which `--offset` can
+/// correct for when lining the ride up with footage.
+fn zip_entry_start<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    index: usize,
+    modified: i64,
+) -> Result<Option<u64>> {
+    let mut rdr = csv::Reader::from_reader(archive.by_index(index)?);
+    let Some(column) = rdr.headers()?.iter().position(|h| h == "Time(s)") else {
+        return Ok(None);
+    };
+
+    let mut last = None;
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        last = record
+            .get(column)
+            .and_then(|t| t.parse::<f64>().ok())
+            .or(last);
+    }
+
+    Ok(last.and_then(|last| u64::try_from(modified - (last * 1000.0).round() as i64).ok()))
+}
+
+fn parse_zip<R: Read + Seek>(rdr: R, entry: &ZipEntry, sink: &mut Sink) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(rdr)?;
+    let csvs = (0..archive.len())
+        .filter(|i| archive.name_for_index(*i).is_some_and(is_ride_csv))
+        .collect::<Vec<_>>();
+
+    let indices = match entry {
+        ZipEntry::First => match csvs.first() {
+            Some(index) => vec![*index],
+            None => bail!(
+                "failed to find inner CSV file, archive contains: {}",
+                archive.file_names().collect::<Vec<_>>().join(", ")
+            ),
+        },
+        ZipEntry::Index(index) => {
+            if *index >= archive.len() {
+                bail!(
+                    "archive entry {} out of range, archive has {} entries",
+                    index,
+                    archive.len()
+                );
+            }
+
+            vec![*index]
+        }
+        ZipEntry::Name(name) => match archive.index_for_name(name) {
+            Some(index) => vec![index],
+            None => bail!(
+                "no entry named {} in archive, archive contains: {}",
+                name,
+                archive.file_names().collect::<Vec<_>>().join(", ")
+            ),
+        },
+        ZipEntry::All => {
+            if csvs.is_empty() {
+                bail!("failed to find inner CSV file");
+            }
+
+            csvs
+        }
+    };
+
+    for index in &indices {
+        let name = archive.name_for_index(*index).unwrap_or_default();
+        if !is_ride_csv(name) {
+            bail!("archive entry {} is not a CSV file", name);
+        }
+    }
+
+    // entries without a modification time keep their order in the archive, after the rest
+    let mut rides = indices
+        .into_iter()
+        .map(|index| Ok((zip_entry_modified(&mut archive, index)?, index)))
+        .collect::<Result<Vec<_>>>()?;
+    rides.sort_by_key(|(modified, _)| (modified.is_none(), *modified));
+
+    // only the first entry's start is needed, each entry follows on from the last
+    let start_time = match rides.first() {
+        Some((Some(modified), index)) => zip_entry_start(&mut archive, *index, *modified)?,
+        _ => None,
+    };
+    sink.header(start_time, vec![])?;
+
+    for (_, index) in rides {
+        sink.next_ride(None);
+        parse_float_control(archive.by_index(index)?, sink)?;
+    }
+
+    Ok(())
+}
+
+/// Float Control ZIP export.
+pub struct Zip;
+
+impl LogFormat for Zip {
+    fn name(&self) -> &'static str {
+        "zip"
+    }
+
+    fn description(&self) -> &'static str {
+        "Float Control ZIP export"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        head.starts_with(b"PK\x03\x04")
+    }
+
+    fn channels(&self) -> &'static [Channel] {
+        float_control::CHANNELS
+    }
+
+    fn parse(
+        &self,
+        mut rdr: Box<dyn Read + Send>,
+        options: &Options,
+        sink: &mut Sink,
+    ) -> Result<()> {
+        // archives need seeking around in
+        let mut archive = vec![];
+        rdr.read_to_end(&mut archive)?;
+        parse_zip(io::Cursor::new(archive), &options.zip_entry, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::parse;
+
+    #[test]
+    fn fc_metric_zip() {
+        let data = parse("test_data/fc_metric.csv.zip", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 1);
+        assert_eq!(data.points[0].speed, 10.0);
+    }
+
+    #[test]
+    fn fc_zip_entries() {
+        let first = parse("test_data/fc_multi.zip", &Options::default()).unwrap();
+        assert_eq!(first.points.len(), 1);
+        assert_eq!(first.points[0].speed, 20.0);
+
+        let options = Options {
+            zip_entry: ZipEntry::Name("ride_a.csv".into()),
+            ..Default::default()
+        };
+        let named = parse("test_data/fc_multi.zip", &options).unwrap();
+        assert_eq!(named.points.len(), 2);
+        assert_eq!(named.points[0].speed, 10.0);
+
+        let options = Options {
+            zip_entry: ZipEntry::Index(0),
+            ..Default::default()
+        };
+        assert!(parse("test_data/fc_multi.zip", &options).is_err());
+
+        let options = Options {
+            zip_entry: ZipEntry::All,
+            ..Default::default()
+        };
+        let all = parse("test_data/fc_multi.zip", &options).unwrap();
+        let speeds = all.points.iter().map(|p| p.speed).collect::<Vec<_>>();
+        assert_eq!(speeds, vec![10.0, 11.0, 20.0]);
+        let indices = all.points.iter().map(|p| p.index).collect::<Vec<_>>();
+        assert_eq!(indices, vec![0, 1, 2]);
+        // 10:00:00 on 2025-01-06, less the 0.2 s ride
+        assert_eq!(all.start_time, Some(1736157599800));
+        // the second entry follows straight on from the first
+        assert!((all.points[2].time - 0.3).abs() < 1e-3);
+    }
+
+    #[test]
+    fn zip_entry_from_str() {
+        assert_eq!("all".parse(), Ok(ZipEntry::All));
+        assert_eq!("2".parse(), Ok(ZipEntry::Index(2)));
+        assert_eq!("ride.csv".parse(), Ok(ZipEntry::Name("ride.csv".into())));
+    }
+}
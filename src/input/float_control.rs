@@ -0,0 +1,483 @@
+use std::fmt;
+use std::io::Read;
+
+use serde_derive::Deserialize;
+
+use super::{header_line, Coordinate, DataPoint, LogFormat, Options, Position, Sink};
+use crate::bail;
+use crate::channel::Channel;
+use crate::err::Result;
+
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+struct FloatControlCsv {
+    #[serde(rename = "Time(s)")]
+    time_seconds: f32,
+    #[serde(rename = "State")]
+    state: Option<String>,
+    #[serde(rename = "State(num)")]
+    state_code: Option<u8>,
+    #[serde(rename = "Distance(km)")]
+    distance_km: Option<f32>,
+    #[serde(rename = "Distance(mi)")]
+    distance_mi: Option<f32>,
+    #[serde(rename = "Speed(km/h)")]
+    speed_kmh: Option<f32>,
+    #[serde(rename = "Speed(mph)")]
+    speed_mph: Option<f32>,
+    #[serde(rename = "Duty%")]
+    duty_cycle: Option<String>,
+    #[serde(rename = "Voltage")]
+    voltage: Option<f32>,
+    #[serde(rename = "I-Battery")]
+    current_battery: Option<f32>,
+    #[serde(rename = "I-Motor")]
+    current_motor: Option<f32>,
+    #[serde(rename = "I-FldWeak")]
+    current_field_weakening: Option<f32>,
+    #[serde(rename = "Requested Amps")]
+    current_requested: Option<f32>,
+    #[serde(rename = "I-Booster")]
+    current_booster: Option<f32>,
+    #[serde(rename = "Altitude(m)")]
+    altitude: Option<f32>,
+    #[serde(rename = "GPS-Lat")]
+    gps_lat: Option<f32>,
+    #[serde(rename = "GPS-Long")]
+    gps_lon: Option<f32>,
+    #[serde(rename = "GPS-Accuracy")]
+    gps_acc: Option<f32>,
+    #[serde(rename = "True Pitch")]
+    true_pitch: Option<f32>,
+    #[serde(rename = "Pitch")]
+    pitch: Option<f32>,
+    #[serde(rename = "Roll")]
+    roll: Option<f32>,
+    #[serde(rename = "Setpoint")]
+    setpoint: Option<f32>,
+    #[serde(rename = "SP-ATR")]
+    setpoint_atr: Option<f32>,
+    #[serde(rename = "SP-Carve")]
+    setpoint_carve: Option<f32>,
+    #[serde(rename = "SP-TrqTlt")]
+    setpoint_torque_tilt: Option<f32>,
+    #[serde(rename = "SP-BrkTlt")]
+    setpoint_brake_tilt: Option<f32>,
+    #[serde(rename = "SP-Remote")]
+    setpoint_remote: Option<f32>,
+    #[serde(rename = "T-Mosfet")]
+    temp_mosfet: Option<f32>,
+    #[serde(rename = "T-Mot")]
+    temp_motor: Option<f32>,
+    #[serde(rename = "T-Batt")]
+    temp_battery: Option<f32>,
+    #[serde(rename = "T-BMS")]
+    temp_bms: Option<f32>,
+    #[serde(rename = "T-Battery")]
+    temp_bms_battery: Option<f32>,
+    #[serde(rename = "BMS-Fault")]
+    bms_fault: Option<u8>,
+    #[serde(rename = "ADC1")]
+    adc1: Option<f32>,
+    #[serde(rename = "ADC2")]
+    adc2: Option<f32>,
+    #[serde(rename = "Motor-Fault")]
+    fault_motor: Option<u8>,
+    #[serde(rename = "Ah")]
+    amp_hours: Option<f32>,
+    #[serde(rename = "Ah Charged")]
+    amp_hours_charged: Option<f32>,
+    #[serde(rename = "Wh")]
+    wh: Option<f32>,
+    #[serde(rename = "Wh Charged")]
+    wh_charged: Option<f32>,
+    #[serde(rename = "ERPM")]
+    erpm: Option<i64>,
+}
+
+/// Columns written by current versions of Float Control, other than `Time(s)` and the unit
+/// specific speed and distance columns, along with the channel each fills in.
+const FLOAT_CONTROL_COLUMNS: &[(&str, &str)] = &[
+    ("State", "state"),
+    ("Duty%", "duty_cycle"),
+    ("Voltage", "batt_voltage"),
+    ("I-Battery", "batt_current"),
+    ("I-Motor", "motor_current"),
+    ("I-FldWeak", "field_weakening"),
+    ("Requested Amps", "current_requested"),
+    ("Pitch", "pitch"),
+    ("Roll", "roll"),
+    ("Setpoint", "setpoint"),
+    ("SP-ATR", "setpoint_atr"),
+    ("SP-Carve", "setpoint_carve"),
+    ("T-Mosfet", "temp_mosfet"),
+    ("T-Mot", "temp_motor"),
+    ("ADC1", "adc1"),
+    ("ADC2", "adc2"),
+    ("Motor-Fault", "fault_motor"),
+    ("Ah", "amp_hours"),
+    ("Ah Charged", "amp_hours_charged"),
+    ("Wh", "watt_hours"),
+    ("Wh Charged", "watt_hours_charged"),
+    ("ERPM", "erpm"),
+    ("Altitude(m)", "altitude"),
+    ("State(num)", "state_code"),
+    ("True Pitch", "true_pitch"),
+    ("SP-TrqTlt", "setpoint_torque_tilt"),
+    ("SP-BrkTlt", "setpoint_brake_tilt"),
+    ("SP-Remote", "setpoint_remote"),
+    ("T-Batt", "temp_battery"),
+    ("I-Booster", "current_booster"),
+    ("GPS-Lat", "latitude"),
+    ("GPS-Long", "longitude"),
+    ("GPS-Accuracy", "gps_accuracy"),
+];
+
+/// Columns only written when a BMS is connected.
+const FLOAT_CONTROL_BMS_COLUMNS: &[&str] = &["T-BMS", "T-Battery", "BMS-Fault"];
+
+/// The layout of a Float Control CSV, as found from its header row.
+///
+/// Float Control has added columns over time, and writes different ones depending on the unit
+/// settings and whether a BMS is connected. Rather than telling app versions apart, the header is
+/// checked against the columns of the current version, so logs from any version read the same way.
+#[derive(Debug, PartialEq)]
+struct FloatControlSchema {
+    imperial: bool,
+    bms: bool,
+    /// Channels which are empty because their columns weren't found.
+    missing: Vec<&'static str>,
+}
+
+impl FloatControlSchema {
+    fn detect(headers: &csv::StringRecord) -> Result<FloatControlSchema> {
+        let has = |column: &str| headers.iter().any(|h| h == column);
+        if !has("Time(s)") {
+            bail!("not a Float Control CSV, missing the Time(s) column");
+        }
+
+        let imperial = has("Speed(mph)");
+        let (speed, distance) = if imperial {
+            (("Speed(mph)", "speed"), ("Distance(mi)", "distance"))
+        } else {
+            (("Speed(km/h)", "speed"), ("Distance(km)", "distance"))
+        };
+
+        Ok(FloatControlSchema {
+            imperial,
+            bms: FLOAT_CONTROL_BMS_COLUMNS.iter().all(|column| has(column)),
+            missing: [speed, distance]
+                .iter()
+                .chain(FLOAT_CONTROL_COLUMNS)
+                .filter(|(column, _)| !has(column))
+                .map(|(_, channel)| *channel)
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Display for FloatControlSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            if self.imperial { "imperial" } else { "metric" },
+            if self.bms { ", with BMS" } else { "" }
+        )
+    }
+}
+
+impl FloatControlCsv {
+    fn speed_kmh(&self) -> f32 {
+        self.speed_kmh
+            .or(self.speed_mph.map(|mph| mph * 1.60934))
+            .unwrap_or(f32::NAN)
+    }
+
+    fn distance_km(&self) -> Option<f32> {
+        self.distance_km.or(self.distance_mi.map(|mi| mi * 1.60934))
+    }
+
+    fn position(&self) -> Option<Position> {
+        let (lat, lon) = self.gps_lat.zip(self.gps_lon)?;
+        // the app logs zeroes until it has a GPS fix
+        if lat == 0.0 && lon == 0.0 {
+            return None;
+        }
+
+        Some(Position {
+            coordinate: Coordinate {
+                latitude: lat as f64,
+                longitude: lon as f64,
+            },
+            altitude: self.altitude,
+            speed: None,
+            accuracy: self.gps_acc,
+        })
+    }
+
+    fn to_data_point(&self, index: usize) -> DataPoint {
+        DataPoint {
+            index,
+            time: self.time_seconds as f64,
+            speed: self.speed_kmh(),
+            duty_cycle: self
+                .duty_cycle
+                .as_deref()
+                .and_then(|duty| duty.trim_end_matches('%').parse::<f32>().ok())
+                .unwrap_or(f32::NAN),
+            motor_current: self.current_motor.unwrap_or(f32::NAN),
+            field_weakening: self.current_field_weakening,
+            current_requested: self.current_requested,
+            current_booster: self.current_booster,
+            erpm: self.erpm.map(|erpm| erpm as f32),
+            temp_motor: self.temp_motor.unwrap_or(f32::NAN),
+            temp_mosfet: self.temp_mosfet.unwrap_or(f32::NAN),
+            // boards without a battery temperature sensor log zeroes
+            temp_battery: self
+                .temp_bms_battery
+                .or(self.temp_battery.filter(|temp| *temp != 0.0)),
+            temp_bms: self.temp_bms,
+            batt_voltage: self.voltage.unwrap_or(f32::NAN),
+            batt_current: self.current_battery.unwrap_or(f32::NAN),
+            battery_percent: None,
+            state: self.state.clone(),
+            state_code: self.state_code,
+            fault_motor: self.fault_motor,
+            fault_bms: self.bms_fault,
+            adc1: self.adc1,
+            adc2: self.adc2,
+            pitch: self.pitch,
+            true_pitch: self.true_pitch,
+            roll: self.roll,
+            setpoint: self.setpoint,
+            setpoint_atr: self.setpoint_atr,
+            setpoint_carve: self.setpoint_carve,
+            setpoint_torque_tilt: self.setpoint_torque_tilt,
+            setpoint_brake_tilt: self.setpoint_brake_tilt,
+            setpoint_remote: self.setpoint_remote,
+            distance: self.distance_km(),
+            amp_hours: self.amp_hours,
+            amp_hours_charged: self.amp_hours_charged,
+            watt_hours: self.wh,
+            watt_hours_charged: self.wh_charged,
+            power: None,
+            efficiency: None,
+            acceleration: None,
+            position: self.position(),
+        }
+    }
+}
+
+pub(super) fn parse_float_control<R: Read>(rdr: R, sink: &mut Sink) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(rdr);
+    let schema = FloatControlSchema::detect(rdr.headers()?)?;
+    if !schema.missing.is_empty() {
+        eprintln!(
+            "Warning: Float Control CSV ({}) is missing columns, these channels will be empty: {}",
+            schema,
+            schema.missing.join(", ")
+        );
+    }
+
+    let mut i = 0;
+    for result in rdr.into_deserialize() {
+        let record: FloatControlCsv = match result {
+            Ok(record) => record,
+            Err(e) => {
+                sink.skip_csv(e)?;
+                continue;
+            }
+        };
+
+        sink.point(record.to_data_point(i))?;
+        i += 1;
+    }
+
+    Ok(())
+}
+
+/// Every channel a Float Control CSV can have, depending on its version and the board's setup.
+pub(super) const CHANNELS: &[Channel] = &[
+    Channel::Speed,
+    Channel::DutyCycle,
+    Channel::MotorCurrent,
+    Channel::FieldWeakening,
+    Channel::CurrentRequested,
+    Channel::CurrentBooster,
+    Channel::Erpm,
+    Channel::TempMotor,
+    Channel::TempMosfet,
+    Channel::TempBattery,
+    Channel::TempBms,
+    Channel::BattVoltage,
+    Channel::BattCurrent,
+    Channel::StateCode,
+    Channel::FaultMotor,
+    Channel::FaultBms,
+    Channel::Adc1,
+    Channel::Adc2,
+    Channel::Pitch,
+    Channel::TruePitch,
+    Channel::Roll,
+    Channel::Setpoint,
+    Channel::SetpointAtr,
+    Channel::SetpointCarve,
+    Channel::SetpointTorqueTilt,
+    Channel::SetpointBrakeTilt,
+    Channel::SetpointRemote,
+    Channel::Distance,
+    Channel::AmpHours,
+    Channel::AmpHoursCharged,
+    Channel::WattHours,
+    Channel::WattHoursCharged,
+    Channel::Latitude,
+    Channel::Longitude,
+    Channel::Altitude,
+    Channel::GpsAccuracy,
+];
+
+/// Float Control CSV.
+pub struct FloatControl;
+
+impl LogFormat for FloatControl {
+    fn name(&self) -> &'static str {
+        "float-control"
+    }
+
+    fn description(&self) -> &'static str {
+        "Float Control CSV"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        header_line(head).contains("Time(s)")
+    }
+
+    fn channels(&self) -> &'static [Channel] {
+        CHANNELS
+    }
+
+    fn parse(&self, rdr: Box<dyn Read + Send>, _: &Options, sink: &mut Sink) -> Result<()> {
+        parse_float_control(rdr, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::parse;
+
+    #[test]
+    fn fc_imperial() {
+        let data = parse("test_data/fc_imperial.csv", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 1);
+        assert_eq!(data.points[0].speed, 16.0934);
+    }
+
+    #[test]
+    fn fc_imperial_bms() {
+        let data = parse("test_data/fc_imperial_bms.csv", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 1);
+        assert_eq!(data.points[0].speed, 16.0934);
+    }
+
+    #[test]
+    fn fc_metric() {
+        let data = parse("test_data/fc_metric.csv", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 1);
+        assert_eq!(data.points[0].speed, 10.0);
+    }
+
+    #[test]
+    fn fc_metric_bms() {
+        let data = parse("test_data/fc_metric_bms.csv", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 1);
+        assert_eq!(data.points[0].speed, 10.0);
+    }
+
+    #[test]
+    fn fc_channels() {
+        let data = parse("test_data/fc_metric_bms.csv", &Options::default()).unwrap();
+        let point = &data.points[0];
+        assert_eq!(point.state.as_deref(), Some("RIDING"));
+        assert_eq!(point.state_code, Some(1));
+        assert_eq!(point.pitch, Some(-0.1));
+        assert_eq!(point.roll, Some(3.4));
+        assert_eq!(point.adc1, Some(3.04));
+        assert_eq!(point.adc2, Some(3.06));
+        assert_eq!(point.watt_hours, Some(0.14));
+        assert_eq!(point.watt_hours_charged, Some(0.02));
+        assert_eq!(point.temp_bms, Some(36.5));
+        assert_eq!(point.temp_battery, Some(29.0));
+        assert_eq!(point.fault_bms, Some(0));
+
+        let position = point.position.as_ref().unwrap();
+        assert_eq!(position.coordinate.latitude, -1.091225f32 as f64);
+        assert_eq!(position.altitude, Some(135.0));
+
+        let data = parse("test_data/fc_imperial.csv", &Options::default()).unwrap();
+        assert_eq!(data.points[0].distance, Some(0.0));
+        assert_eq!(data.points[0].temp_bms, None);
+    }
+
+    #[test]
+    fn fc_schema() {
+        let headers = |path: &str| {
+            csv::Reader::from_path(path)
+                .unwrap()
+                .headers()
+                .unwrap()
+                .clone()
+        };
+
+        let schema = FloatControlSchema::detect(&headers("test_data/fc_imperial_bms.csv")).unwrap();
+        assert_eq!(
+            schema,
+            FloatControlSchema {
+                imperial: true,
+                bms: true,
+                missing: vec![]
+            }
+        );
+
+        let schema = FloatControlSchema::detect(&headers("test_data/fc_metric.csv")).unwrap();
+        assert_eq!(schema.to_string(), "metric");
+        assert!(schema.missing.is_empty());
+
+        let schema = FloatControlSchema::detect(&headers("test_data/fc_old.csv")).unwrap();
+        assert_eq!(
+            schema.missing,
+            vec![
+                "adc2",
+                "setpoint_remote",
+                "current_booster",
+                "latitude",
+                "longitude",
+                "gps_accuracy"
+            ]
+        );
+
+        assert!(FloatControlSchema::detect(&headers("test_data/vesc_tool.csv")).is_err());
+    }
+
+    #[test]
+    fn fc_missing_columns() {
+        let data = parse("test_data/fc_old.csv", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 1);
+
+        let point = &data.points[0];
+        assert_eq!(point.speed, 10.0);
+        assert_eq!(point.adc1, Some(3.04));
+        assert_eq!(point.adc2, None);
+        assert_eq!(point.setpoint_remote, None);
+        assert_eq!(point.current_booster, None);
+        assert_eq!(point.position, None);
+    }
+
+    #[test]
+    fn fc_timeline() {
+        let data = parse("test_data/fc_metric.csv", &Options::default()).unwrap();
+        assert_eq!(data.start_time, None);
+        assert_eq!(data.points[0].time, 0.12f32 as f64);
+    }
+}
@@ -0,0 +1,493 @@
+use std::fmt;
+use std::io::Read;
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_derive::Deserialize;
+
+use super::{head_text, Coordinate, DataPoint, LogFormat, Options, Position, Sink};
+use crate::bail;
+use crate::channel::Channel;
+use crate::err::Result;
+
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+struct FloatyLog {
+    #[serde(rename = "timestamp")]
+    timestamp: u64,
+    #[serde(rename = "speed")]
+    speed: Option<f64>,
+    #[serde(rename = "dutyCycle")]
+    duty_cycle: Option<f64>,
+    #[serde(rename = "batteryVolts")]
+    battery_volts: Option<f64>,
+    #[serde(rename = "batteryPercent")]
+    battery_percent: f64,
+    #[serde(rename = "batteryCurrent")]
+    battery_current: Option<f64>,
+    #[serde(rename = "motorCurrent")]
+    motor_current: Option<f64>,
+    #[serde(rename = "motorTemp")]
+    motor_temp: f64,
+    #[serde(rename = "controllerTemp")]
+    controller_temp: f64,
+    #[serde(rename = "tripDistance")]
+    trip_distance: f64,
+    #[serde(rename = "lifeDistance")]
+    life_distance: f64,
+    #[serde(rename = "remainingDistance")]
+    remaining_distance: f64,
+    #[serde(rename = "rollAngle")]
+    roll_angle: f64,
+    #[serde(rename = "pitchAngle")]
+    pitch_angle: f64,
+    #[serde(rename = "truePitchAngle")]
+    true_pitch_angle: f64,
+    #[serde(rename = "inputTilt")]
+    input_tilt: f64,
+    #[serde(rename = "throttle")]
+    throttle: f64,
+    #[serde(rename = "ampHours")]
+    amp_hours: f64,
+    #[serde(rename = "wattHours")]
+    watt_hours: f64,
+    #[serde(rename = "state")]
+    state: f64,
+    #[serde(rename = "switchState")]
+    switch_state: f64,
+    #[serde(rename = "setpointAdjustmentType")]
+    setpoint_adjustment_type: f64,
+    #[serde(rename = "faultCode")]
+    fault_code: f64,
+    #[serde(rename = "adc1")]
+    adc1: f64,
+    #[serde(rename = "adc2")]
+    adc2: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct FloatyLocation {
+    #[serde(rename = "timestamp")]
+    timestamp: f64,
+    #[serde(rename = "latitude")]
+    latitude: f64,
+    #[serde(rename = "longitude")]
+    longitude: f64,
+    #[serde(rename = "altitude")]
+    altitude: Option<f64>,
+    /// Metres per second, negative if invalid.
+    #[serde(rename = "speed")]
+    speed: Option<f64>,
+    /// Metres, negative if invalid.
+    #[serde(rename = "accuracy")]
+    accuracy: Option<f64>,
+}
+
+/// How long a GPS fix is attached to the logs after it, in milliseconds. Logs further from the last
+/// fix, such as after the GPS signal is lost, have no position rather than a stale one.
+const MAX_FIX_AGE: f64 = 5000.0;
+
+impl FloatyLocation {
+    fn coordinate(&self) -> Coordinate {
+        Coordinate {
+            latitude: self.latitude,
+            longitude: self.longitude,
+        }
+    }
+
+    fn to_position(&self) -> Position {
+        Position {
+            coordinate: self.coordinate(),
+            altitude: self.altitude.map(|alt| alt as f32),
+            speed: self
+                .speed
+                .filter(|speed| *speed >= 0.0)
+                .map(|speed| (speed * 3.6) as f32),
+            accuracy: self
+                .accuracy
+                .filter(|acc| *acc >= 0.0)
+                .map(|acc| acc as f32),
+        }
+    }
+}
+
+impl FloatyLog {
+    fn to_data_point(
+        &self,
+        start_time: u64,
+        index: usize,
+        location: Option<&FloatyLocation>,
+    ) -> DataPoint {
+        DataPoint {
+            index,
+            time: (self.timestamp as i64 - start_time as i64) as f64 / 1000.0,
+            speed: self.speed.unwrap_or(f64::NAN) as f32,
+            duty_cycle: self.duty_cycle.unwrap_or(f64::NAN) as f32,
+            motor_current: self.motor_current.unwrap_or(f64::NAN) as f32,
+            field_weakening: None,
+            current_requested: None,
+            current_booster: None,
+            erpm: None,
+            temp_motor: self.motor_temp as f32,
+            temp_mosfet: self.controller_temp as f32,
+            temp_battery: None,
+            temp_bms: None,
+            batt_voltage: self.battery_volts.unwrap_or(f64::NAN) as f32,
+            batt_current: self.battery_current.unwrap_or(f64::NAN) as f32,
+            battery_percent: Some((self.battery_percent * 100.0) as f32),
+            state: None,
+            state_code: Some(self.state as u8),
+            fault_motor: Some(self.fault_code as u8),
+            fault_bms: None,
+            adc1: Some(self.adc1 as f32),
+            adc2: Some(self.adc2 as f32),
+            pitch: Some(self.pitch_angle as f32),
+            true_pitch: Some(self.true_pitch_angle as f32),
+            roll: Some(self.roll_angle as f32),
+            setpoint: None,
+            setpoint_atr: None,
+            setpoint_carve: None,
+            setpoint_torque_tilt: None,
+            setpoint_brake_tilt: None,
+            setpoint_remote: None,
+            distance: Some(self.trip_distance as f32),
+            amp_hours: Some(self.amp_hours as f32),
+            amp_hours_charged: None,
+            watt_hours: Some(self.watt_hours as f32),
+            watt_hours_charged: None,
+            power: None,
+            efficiency: None,
+            acceleration: None,
+            position: location.map(FloatyLocation::to_position),
+        }
+    }
+}
+
+/// Decodes a path encoded with Google's polyline algorithm.
+///
+/// See: https://developers.google.com/maps/documentation/utilities/polylinealgorithm
+fn decode_polyline(polyline: &str) -> Result<Vec<Coordinate>> {
+    let mut coords = vec![];
+    let mut bytes = polyline.bytes();
+    let mut next_value = || -> Result<Option<i64>> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let b = match bytes.next() {
+                Some(b) => b as i64 - 63,
+                None if shift == 0 => return Ok(None),
+                None => bail!("truncated polyline"),
+            };
+            if !(0..64).contains(&b) || shift > 60 {
+                bail!("invalid character in polyline");
+            }
+
+            result |= (b & 0x1f) << shift;
+            shift += 5;
+            if b < 0x20 {
+                break;
+            }
+        }
+
+        Ok(Some(if result & 1 == 1 {
+            !(result >> 1)
+        } else {
+            result >> 1
+        }))
+    };
+
+    let (mut lat, mut lon) = (0i64, 0i64);
+    while let Some(d_lat) = next_value()? {
+        let Some(d_lon) = next_value()? else {
+            bail!("truncated polyline");
+        };
+
+        lat += d_lat;
+        lon += d_lon;
+        coords.push(Coordinate {
+            latitude: lat as f64 / 1e5,
+            longitude: lon as f64 / 1e5,
+        });
+    }
+
+    Ok(coords)
+}
+
+/// Streams a Floaty JSON document.
+///
+/// Only the `logs` array is streamed: `startTime`, `polyline` and `locations` are read as they're
+/// met, and the Floaty app writes them before `logs`. If `logs` were to come first, times are taken
+/// relative to the first log and no positions are attached.
+struct FloatyVisitor<'a> {
+    sink: &'a mut Sink,
+}
+
+impl<'de> Visitor<'de> for FloatyVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a Floaty ride")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<(), A::Error> {
+        let FloatyVisitor { sink } = self;
+
+        let mut start_time = None;
+        let mut polyline: Option<String> = None;
+        let mut locations: Vec<FloatyLocation> = vec![];
+        let mut has_logs = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "startTime" => start_time = Some(map.next_value()?),
+                "polyline" => polyline = map.next_value()?,
+                "locations" => locations = map.next_value()?,
+                "logs" => {
+                    locations.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+                    let route = match polyline.as_deref() {
+                        Some(polyline) if !polyline.is_empty() => {
+                            decode_polyline(polyline).map_err(de::Error::custom)?
+                        }
+                        _ => locations.iter().map(FloatyLocation::coordinate).collect(),
+                    };
+                    sink.header(start_time, route).map_err(de::Error::custom)?;
+
+                    map.next_value_seed(FloatyLogs {
+                        sink: &mut *sink,
+                        start_time,
+                        locations: &locations,
+                    })?;
+                    has_logs = true;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        if !has_logs {
+            return Err(de::Error::missing_field("logs"));
+        }
+
+        Ok(())
+    }
+}
+
+struct FloatyLogs<'a> {
+    sink: &'a mut Sink,
+    start_time: Option<u64>,
+    locations: &'a [FloatyLocation],
+}
+
+impl<'de> DeserializeSeed<'de> for FloatyLogs<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FloatyLogs<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of Floaty logs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+        let mut start_time = self.start_time;
+
+        // attach the most recent GPS fix to each log, unless it's too old
+        let mut locations = self.locations.iter().peekable();
+        let mut location = None;
+        let mut i = 0;
+        let mut n = 0;
+        while let Some(log) = seq.next_element::<serde_json::Value>()? {
+            n += 1;
+            let log = match serde_json::from_value::<FloatyLog>(log) {
+                Ok(log) => log,
+                Err(e) => {
+                    self.sink
+                        .skip(format!("log {}", n), e)
+                        .map_err(de::Error::custom)?;
+                    continue;
+                }
+            };
+
+            let start_time = *start_time.get_or_insert(log.timestamp);
+            while let Some(next) = locations.next_if(|l| l.timestamp <= log.timestamp as f64) {
+                location = Some(next);
+            }
+            let fix = location.filter(|l| log.timestamp as f64 - l.timestamp <= MAX_FIX_AGE);
+
+            self.sink
+                .point(log.to_data_point(start_time, i, fix))
+                .map_err(de::Error::custom)?;
+            i += 1;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_floaty<R: Read>(rdr: R, sink: &mut Sink) -> Result<()> {
+    let mut deserializer = serde_json::Deserializer::from_reader(rdr);
+    match deserializer.deserialize_map(FloatyVisitor { sink: &mut *sink }) {
+        // keep what was read of a file that was cut off while being written
+        Err(e) if e.is_eof() && sink.lenient => {
+            return sink.skip(format!("line {}", e.line()), "unexpected end of file");
+        }
+        result => result?,
+    }
+    deserializer.end()?;
+
+    Ok(())
+}
+
+/// Floaty JSON.
+pub struct Floaty;
+
+impl LogFormat for Floaty {
+    fn name(&self) -> &'static str {
+        "floaty"
+    }
+
+    fn description(&self) -> &'static str {
+        "Floaty JSON"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        // the `logs` array may be further in than we can see, but `startTime` comes early on
+        let text = head_text(head);
+        text.starts_with('{') && (text.contains("\"logs\"") || text.contains("\"startTime\""))
+    }
+
+    fn channels(&self) -> &'static [Channel] {
+        &[
+            Channel::Speed,
+            Channel::DutyCycle,
+            Channel::MotorCurrent,
+            Channel::TempMotor,
+            Channel::TempMosfet,
+            Channel::BattVoltage,
+            Channel::BattCurrent,
+            Channel::BatteryPercent,
+            Channel::StateCode,
+            Channel::FaultMotor,
+            Channel::Adc1,
+            Channel::Adc2,
+            Channel::Pitch,
+            Channel::TruePitch,
+            Channel::Roll,
+            Channel::Distance,
+            Channel::AmpHours,
+            Channel::WattHours,
+            Channel::Latitude,
+            Channel::Longitude,
+            Channel::Altitude,
+            Channel::GpsSpeed,
+            Channel::GpsAccuracy,
+        ]
+    }
+
+    fn parse(&self, rdr: Box<dyn Read + Send>, _: &Options, sink: &mut Sink) -> Result<()> {
+        parse_floaty(rdr, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{parse, Stream};
+
+    #[test]
+    fn floaty_json() {
+        let data = parse("test_data/floaty.json", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 3);
+        assert_eq!(data.points[0].battery_percent, Some(92.0));
+    }
+
+    #[test]
+    fn floaty_timeline() {
+        let data = parse("test_data/floaty.json", &Options::default()).unwrap();
+        assert_eq!(data.start_time, Some(100));
+        let times = data.points.iter().map(|p| p.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![0.005, 0.015, 0.025]);
+    }
+
+    #[test]
+    fn floaty_json_locations() {
+        let data = parse("test_data/floaty.json", &Options::default()).unwrap();
+        assert!(data.points[0].position.is_none());
+
+        let position = data.points[1].position.as_ref().unwrap();
+        assert_eq!(position.coordinate.latitude, -1.0);
+        assert_eq!(position.coordinate.longitude, 1.5);
+        assert_eq!(position.altitude, Some(122.16287));
+
+        let position = data.points[2].position.as_ref().unwrap();
+        assert_eq!(position.coordinate.latitude, -1.1);
+        assert_eq!(position.coordinate.longitude, 1.6);
+
+        assert_eq!(data.route.len(), 5);
+    }
+
+    #[test]
+    fn stale_locations() {
+        let log = |timestamp: u64| {
+            let mut log = serde_json::json!({
+                "batteryPercent": 1, "motorTemp": 0, "controllerTemp": 0, "tripDistance": 0,
+                "lifeDistance": 0, "remainingDistance": 0, "rollAngle": 0, "pitchAngle": 0,
+                "truePitchAngle": 0, "inputTilt": 0, "throttle": 0, "ampHours": 0, "wattHours": 0,
+                "state": 1, "switchState": 0, "setpointAdjustmentType": 0, "faultCode": 0,
+                "adc1": 0, "adc2": 0,
+            });
+            log["timestamp"] = timestamp.into();
+            log
+        };
+        // the GPS is lost after its only fix
+        let json = serde_json::json!({
+            "startTime": 0,
+            "locations": [{"timestamp": 1000, "latitude": 1, "longitude": 2, "speed": 5}],
+            "logs": [log(2000), log(6000), log(6500)],
+        })
+        .to_string();
+
+        let data = Stream::spawn(false, move |sink| parse_floaty(json.as_bytes(), sink))
+            .unwrap()
+            .into_ride()
+            .unwrap();
+        let speeds = data
+            .points
+            .iter()
+            .map(|p| p.position.as_ref().and_then(|p| p.speed))
+            .collect::<Vec<_>>();
+        assert_eq!(speeds, vec![Some(18.0), Some(18.0), None]);
+    }
+
+    #[test]
+    fn polyline() {
+        let coords = decode_polyline("_p~iF~ps|U_ulLnnqC_mqNvxq`@").unwrap();
+        assert_eq!(
+            coords,
+            vec![
+                Coordinate {
+                    latitude: 38.5,
+                    longitude: -120.2
+                },
+                Coordinate {
+                    latitude: 40.7,
+                    longitude: -120.95
+                },
+                Coordinate {
+                    latitude: 43.252,
+                    longitude: -126.453
+                },
+            ]
+        );
+
+        assert!(decode_polyline("_p~iF").is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use serde_derive::Deserialize;
+
+use super::{unix_millis, DataPoint, LogFormat, Options, Sink};
+use crate::bail;
+use crate::channel::Channel;
+use crate::err::Result;
+
+/// How the time column of a mapped CSV is written.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    /// Seconds since the log started.
+    Seconds,
+    /// Milliseconds since the log started.
+    Milliseconds,
+    /// Seconds since the unix epoch.
+    Unix,
+    /// Milliseconds since the unix epoch.
+    UnixMs,
+    /// A date and time, e.g. `2024-09-16T09:36:49.450Z`.
+    Datetime,
+    /// A time of day, e.g. `09:36:49.450`.
+    TimeOfDay,
+}
+
+impl TimeFormat {
+    /// Parses a time as milliseconds.
+    fn parse(&self, value: &str) -> Option<f64> {
+        let value = value.trim();
+        match self {
+            TimeFormat::Seconds | TimeFormat::Unix => value.parse::<f64>().ok().map(|s| s * 1000.0),
+            TimeFormat::Milliseconds | TimeFormat::UnixMs => value.parse().ok(),
+            TimeFormat::Datetime => parse_datetime(value).map(|ms| ms as f64),
+            TimeFormat::TimeOfDay => parse_time_of_day(value).map(|s| s * 1000.0),
+        }
+    }
+
+    /// Whether times are wall clock times, rather than relative to the start of the log.
+    fn is_absolute(&self) -> bool {
+        matches!(
+            self,
+            TimeFormat::Unix | TimeFormat::UnixMs | TimeFormat::Datetime
+        )
+    }
+}
+
+/// Parses a time of day such as `09:36` or `09:36:49.450` as seconds since midnight.
+fn parse_time_of_day(value: &str) -> Option<f64> {
+    let mut parts = value.split(':');
+    let hour = parts.next()?.parse::<u32>().ok()?;
+    let minute = parts.next()?.parse::<u32>().ok()?;
+    let second = match parts.next() {
+        Some(second) => second.parse::<f64>().ok()?,
+        None => 0.0,
+    };
+    if parts.next().is_some() || hour > 23 || minute > 59 || !(0.0..61.0).contains(&second) {
+        return None;
+    }
+
+    Some((hour * 3600 + minute * 60) as f64 + second)
+}
+
+/// Parses an ISO 8601 date and time, such as `2024-09-16T09:36:49.450Z` or
+/// `2024-09-16 19:36:49+10:00`, as milliseconds since the unix epoch. Times without a timezone are
+/// taken as UTC.
+pub fn parse_datetime(value: &str) -> Option<i64> {
+    let (date, time) = value.split_once(['T', ' '])?;
+
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?.parse::<u32>().ok()?;
+    let day = parts.next()?.parse::<u32>().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, offset_minutes) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else if let Some(i) = time.rfind(['+', '-']) {
+        let (hours, minutes) = time[i + 1..].split_once(':')?;
+        let minutes = hours.parse::<i64>().ok()? * 60 + minutes.parse::<i64>().ok()?;
+        let sign = if time[i..].starts_with('-') { -1 } else { 1 };
+        (&time[..i], sign * minutes)
+    } else {
+        (time, 0)
+    };
+
+    let seconds = parse_time_of_day(time)?;
+    Some(unix_millis(year, month, day, 0, 0, seconds) - offset_minutes * 60 * 1000)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MappedTime {
+    column: String,
+    #[serde(default = "MappedTime::default_format")]
+    format: TimeFormat,
+}
+
+impl MappedTime {
+    fn default_format() -> TimeFormat {
+        TimeFormat::Seconds
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MappedColumn {
+    channel: Channel,
+    /// Unit of the column, after scaling. Defaults to the unit of the channel.
+    unit: Option<String>,
+    #[serde(default = "MappedColumn::default_scale")]
+    scale: f64,
+}
+
+impl MappedColumn {
+    fn default_scale() -> f64 {
+        1.0
+    }
+
+    fn value(&self, value: f64) -> Result<f64> {
+        let value = value * self.scale;
+        match &self.unit {
+            Some(unit) => self.channel.convert(value, unit),
+            None => Ok(value),
+        }
+    }
+}
+
+/// Describes how to read an arbitrary CSV, loaded from a JSON file such as:
+///
+/// ```json
+/// {
+///   "delimiter": ";",
+///   "time": { "column": "timestamp", "format": "unix_ms" },
+///   "columns": {
+///     "Speed (mph)": { "channel": "speed", "unit": "mph" },
+///     "Pack mV": { "channel": "batt_voltage", "unit": "mV" },
+///     "Temp x10": { "channel": "temp_motor", "scale": 0.1 }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mapping {
+    #[serde(default = "Mapping::default_delimiter")]
+    delimiter: char,
+    time: MappedTime,
+    columns: BTreeMap<String, MappedColumn>,
+}
+
+impl Mapping {
+    fn default_delimiter() -> char {
+        ','
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Mapping> {
+        let path = path.as_ref();
+        let mapping: Mapping = serde_json::from_reader(BufReader::new(File::open(path)?))
+            .map_err(|e| format!("invalid mapping file {}: {}", path.display(), e))?;
+
+        if !mapping.delimiter.is_ascii() {
+            bail!("mapping delimiter must be a single ASCII character");
+        }
+        for column in mapping.columns.values() {
+            column.value(1.0)?;
+        }
+
+        Ok(mapping)
+    }
+}
+
+fn parse_mapped<R: Read>(rdr: R, mapping: &Mapping, sink: &mut Sink) -> Result<()> {
+    const MS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter as u8)
+        .from_reader(rdr);
+    let headers = rdr.headers()?.clone();
+    let find = |name: &str| match headers.iter().position(|h| h.trim() == name) {
+        Some(index) => Ok(index),
+        None => Err(format!("column {} not found in CSV header", name)),
+    };
+
+    let time_column = find(&mapping.time.column)?;
+    let columns = mapping
+        .columns
+        .iter()
+        .map(|(name, column)| Ok((find(name)?, name, column)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut start = None;
+    let mut record = csv::StringRecord::new();
+    let mut i = 0;
+    loop {
+        match rdr.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                sink.skip_csv(e)?;
+                continue;
+            }
+        }
+
+        let line = format!("line {}", record.position().map_or(0, |pos| pos.line()));
+        let time = record.get(time_column).unwrap_or_default();
+        let Some(time) = mapping.time.format.parse(time) else {
+            sink.skip(line, format!("invalid time {}", time))?;
+            continue;
+        };
+
+        let start = *start.get_or_insert(time);
+        if i == 0 && mapping.time.format.is_absolute() {
+            sink.header(Some(time.round() as u64), vec![])?;
+        }
+
+        let mut elapsed = time - start;
+        if mapping.time.format == TimeFormat::TimeOfDay {
+            // times of day wrap around at midnight
+            elapsed = elapsed.rem_euclid(MS_PER_DAY);
+        }
+
+        let mut point = DataPoint {
+            index: i,
+            time: elapsed / 1000.0,
+            ..Default::default()
+        };
+        let mut invalid = None;
+        for (index, name, column) in &columns {
+            let value = record.get(*index).unwrap_or_default().trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            let Ok(value) = value.parse::<f64>() else {
+                invalid = Some(format!("invalid value {} in column {}", value, name));
+                break;
+            };
+            column.channel.set(&mut point, column.value(value)?);
+        }
+        if let Some(reason) = invalid {
+            sink.skip(line, reason)?;
+            continue;
+        }
+
+        // drop partial positions, e.g. a latitude without a longitude
+        if point
+            .position
+            .as_ref()
+            .is_some_and(|p| p.coordinate.latitude.is_nan() || p.coordinate.longitude.is_nan())
+        {
+            point.position = None;
+        }
+
+        sink.point(point)?;
+        i += 1;
+    }
+
+    Ok(())
+}
+
+/// Any CSV, read according to a [`Mapping`].
+pub struct Mapped;
+
+impl LogFormat for Mapped {
+    fn name(&self) -> &'static str {
+        "mapped"
+    }
+
+    fn description(&self) -> &'static str {
+        "Any CSV, read according to a --mapping file"
+    }
+
+    fn detect(&self, _: &[u8]) -> bool {
+        // only used when given a mapping
+        false
+    }
+
+    fn channels(&self) -> &'static [Channel] {
+        Channel::ALL
+    }
+
+    fn parse(&self, rdr: Box<dyn Read + Send>, options: &Options, sink: &mut Sink) -> Result<()> {
+        let Some(mapping) = &options.mapping else {
+            bail!("a mapping file is needed to read the mapped format, see --mapping");
+        };
+
+        parse_mapped(rdr, mapping, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::parse;
+
+    #[test]
+    fn mapped_csv() {
+        let options = Options {
+            mapping: Some(Mapping::load("test_data/mapping.json").unwrap()),
+            ..Default::default()
+        };
+        let data = parse("test_data/mapped.csv", &options).unwrap();
+        assert_eq!(data.start_time, Some(1726479409450));
+        assert_eq!(data.points.len(), 2);
+
+        let times = data.points.iter().map(|p| p.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![0.0, 1.5]);
+
+        let point = &data.points[0];
+        assert_eq!(point.speed, 16.09344);
+        assert_eq!(point.batt_voltage, 80.0);
+        assert_eq!(point.batt_current, 2.5);
+        assert_eq!(point.temp_motor, 100.0);
+        assert_eq!(point.position.as_ref().unwrap().coordinate.latitude, -1.5);
+
+        let point = &data.points[1];
+        assert!(point.batt_current.is_nan());
+        assert!(point.position.is_none());
+
+        // the mapping is used regardless of what the file looks like
+        assert!(parse("test_data/fc_metric.csv", &options).is_err());
+    }
+
+    #[test]
+    fn datetimes() {
+        assert_eq!(
+            parse_datetime("2024-09-16T09:36:49.450Z"),
+            Some(1726479409450)
+        );
+        assert_eq!(
+            parse_datetime("2024-09-16 09:36:49.45"),
+            Some(1726479409450)
+        );
+        assert_eq!(
+            parse_datetime("2024-09-16T19:36:49.450+10:00"),
+            Some(1726479409450)
+        );
+        assert_eq!(parse_datetime("2024-09-16T09:36Z"), Some(1726479360000));
+        assert_eq!(parse_datetime("2024-09-16"), None);
+        assert_eq!(parse_datetime("2024-13-16T09:36:49Z"), None);
+        assert_eq!(parse_time_of_day("23:59:59.5"), Some(86399.5));
+        assert_eq!(parse_time_of_day("24:00"), None);
+    }
+}
@@ -0,0 +1,819 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::bail;
+use crate::channel::Channel;
+use crate::err::Result;
+
+mod archive;
+mod float_control;
+mod floaty;
+mod mapped;
+mod vesc_tool;
+
+pub use archive::ZipEntry;
+pub use mapped::{parse_datetime, Mapping};
+
+/// A single point on the earth, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinate {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// A GPS fix recorded during the ride.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub coordinate: Coordinate,
+    /// Metres above sea level.
+    pub altitude: Option<f32>,
+    /// Ground speed reported by the GPS, in km/h.
+    pub speed: Option<f32>,
+    /// Horizontal accuracy, in metres.
+    pub accuracy: Option<f32>,
+}
+
+/// A ride read into memory: the telemetry samples plus any ride-level data. Only tests need the
+/// whole ride at once, rendering streams it instead (see [`Stream`]).
+#[cfg(test)]
+pub struct Ride {
+    pub start_time: Option<u64>,
+    pub route: Vec<Coordinate>,
+    pub points: Vec<DataPoint>,
+    pub diagnostics: Diagnostics,
+}
+
+/// How many skipped rows are warned about, and kept in the [`Diagnostics`].
+const REPORTED_ERRORS: usize = 10;
+
+/// What a parser read, and what it skipped over in lenient mode.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Diagnostics {
+    pub rows_read: usize,
+    pub rows_skipped: usize,
+    /// Where and why the first [`REPORTED_ERRORS`] skipped rows couldn't be parsed.
+    pub errors: Vec<String>,
+}
+
+impl Diagnostics {
+    fn add(&mut self, other: Diagnostics) {
+        self.rows_read += other.rows_read;
+        self.rows_skipped += other.rows_skipped;
+        let room = REPORTED_ERRORS.saturating_sub(self.errors.len());
+        self.errors.extend(other.errors.into_iter().take(room));
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Read {} rows, skipped {}",
+            self.rows_read, self.rows_skipped
+        )
+    }
+}
+
+/// How many points a parser may read ahead of the consumer.
+const STREAM_BUFFER: usize = 256;
+
+/// A ride whose points are parsed as they're consumed.
+///
+/// Parsing happens on a background thread which hands points over through a bounded channel, so
+/// only a small window of the ride is held in memory at once.
+pub struct Stream {
+    /// Wall clock time the ride started at (milliseconds since the unix epoch), if
+    /// the log records one.
+    pub start_time: Option<u64>,
+    /// The path of the ride, if the log provides one.
+    pub route: Vec<Coordinate>,
+    messages: mpsc::IntoIter<Message>,
+    /// Whether to print the [`Diagnostics`] once the ride has been read.
+    report: bool,
+    diagnostics: Diagnostics,
+}
+
+impl Stream {
+    /// Runs `parse` on a background thread, returning once it has sent the ride-level data.
+    ///
+    /// When `lenient`, rows the parser can't read are skipped with a warning (see [`Sink::skip`])
+    /// and the totals are printed once the ride has been read.
+    fn spawn<F>(lenient: bool, parse: F) -> Result<Stream>
+    where
+        F: FnOnce(&mut Sink) -> Result<()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel(STREAM_BUFFER);
+        thread::spawn(move || {
+            let mut sink = Sink {
+                tx,
+                started: false,
+                lenient,
+                joiner: None,
+                diagnostics: Diagnostics::default(),
+            };
+            // rides without any points still need a header
+            let result = parse(&mut sink).and_then(|_| sink.header(None, vec![]));
+            let message = match result {
+                Ok(()) => Message::Done(std::mem::take(&mut sink.diagnostics)),
+                Err(e) => Message::Error(e.to_string()),
+            };
+            let _ = sink.tx.send(message);
+        });
+
+        match rx.recv() {
+            Ok(Message::Header { start_time, route }) => Ok(Stream {
+                start_time,
+                route,
+                messages: rx.into_iter(),
+                report: lenient,
+                diagnostics: Diagnostics::default(),
+            }),
+            Ok(Message::Error(e)) => Err(e.into()),
+            Ok(Message::Point(_) | Message::Done(_)) => unreachable!("parser skipped the header"),
+            Err(_) => bail!("parser exited unexpectedly"),
+        }
+    }
+
+    /// Reads the rest of the ride into memory.
+    #[cfg(test)]
+    pub fn into_ride(mut self) -> Result<Ride> {
+        let points = self.by_ref().collect::<Result<_>>()?;
+        Ok(Ride {
+            start_time: self.start_time,
+            route: self.route,
+            points,
+            diagnostics: self.diagnostics,
+        })
+    }
+}
+
+impl Iterator for Stream {
+    type Item = Result<DataPoint>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.messages.next()? {
+                Message::Point(point) => return Some(Ok(*point)),
+                Message::Error(e) => return Some(Err(e.into())),
+                Message::Done(diagnostics) => {
+                    if self.report {
+                        eprintln!("{}", diagnostics);
+                    }
+                    self.diagnostics = diagnostics;
+                }
+                Message::Header { .. } => unreachable!("header sent twice"),
+            }
+        }
+    }
+}
+
+enum Message {
+    Header {
+        start_time: Option<u64>,
+        route: Vec<Coordinate>,
+    },
+    Point(Box<DataPoint>),
+    Error(String),
+    /// The parser finished without error.
+    Done(Diagnostics),
+}
+
+/// Where a parser sends the ride it reads.
+pub struct Sink {
+    tx: SyncSender<Message>,
+    started: bool,
+    lenient: bool,
+    /// Set once several rides are being joined, see [`Sink::next_ride`].
+    joiner: Option<Joiner>,
+    diagnostics: Diagnostics,
+}
+
+impl Sink {
+    /// Sends the ride-level data. This must come before any points, and is ignored after the
+    /// first call.
+    pub fn header(&mut self, start_time: Option<u64>, route: Vec<Coordinate>) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            self.send(Message::Header { start_time, route })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn point(&mut self, point: DataPoint) -> Result<()> {
+        self.header(None, vec![])?;
+        self.diagnostics.rows_read += 1;
+
+        let point = match &mut self.joiner {
+            Some(joiner) => joiner.place(point),
+            None => point,
+        };
+        self.send(Message::Point(Box::new(point)))
+    }
+
+    /// Handles a row which couldn't be parsed: it's an error unless parsing leniently, in which
+    /// case the row is skipped and noted in the [`Diagnostics`], with a warning for the first
+    /// [`REPORTED_ERRORS`].
+    pub fn skip(&mut self, location: impl fmt::Display, reason: impl fmt::Display) -> Result<()> {
+        if !self.lenient {
+            bail!("{}: {}", location, reason);
+        }
+
+        self.diagnostics.rows_read += 1;
+        self.diagnostics.rows_skipped += 1;
+        if self.diagnostics.errors.len() < REPORTED_ERRORS {
+            let error = format!("{}: {}", location, reason);
+            eprintln!("Warning: skipped {}", error);
+            self.diagnostics.errors.push(error);
+            if self.diagnostics.errors.len() == REPORTED_ERRORS {
+                eprintln!("Warning: further skipped rows won't be shown");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Skips a CSV row which failed to read, see [`Sink::skip`]. I/O errors can't be skipped.
+    fn skip_csv(&mut self, e: csv::Error) -> Result<()> {
+        let line = match e.position() {
+            Some(position) if !e.is_io_error() => position.line(),
+            _ => return Err(e.into()),
+        };
+
+        match e.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => self.skip(format!("line {}", line), err),
+            csv::ErrorKind::UnequalLengths {
+                expected_len, len, ..
+            } => self.skip(
+                format!("line {}", line),
+                format!("expected {} fields, found {}", expected_len, len),
+            ),
+            _ => self.skip(format!("line {}", line), &e),
+        }
+    }
+
+    /// Sends the points of another ride, which should follow a call to [`Sink::next_ride`].
+    ///
+    /// Errors are prefixed with `name`, to tell which ride they came from.
+    fn relay(&mut self, name: &str, mut stream: Stream) -> Result<()> {
+        // the diagnostics are reported for the joined ride instead
+        stream.report = false;
+        for point in stream.by_ref() {
+            match point {
+                Ok(point) => {
+                    self.header(None, vec![])?;
+                    let point = self.joiner.get_or_insert_with(Joiner::default).place(point);
+                    self.send(Message::Point(Box::new(point)))?;
+                }
+                Err(e) => bail!("{}: {}", name, e),
+            }
+        }
+
+        self.diagnostics.add(stream.diagnostics);
+        Ok(())
+    }
+
+    /// Begins the next of several rides which are joined onto one timeline, see [`Joiner`].
+    fn next_ride(&mut self, start: Option<u64>) {
+        self.joiner
+            .get_or_insert_with(Joiner::default)
+            .next_ride(start);
+    }
+
+    fn send(&self, message: Message) -> Result<()> {
+        // fails once the stream has been dropped, which stops the parser
+        if self.tx.send(message).is_err() {
+            bail!("stream closed");
+        }
+
+        Ok(())
+    }
+}
+
+/// Lays several rides out one after another on a single timeline.
+#[derive(Default)]
+struct Joiner {
+    rides: usize,
+    first_start: Option<u64>,
+    start: Option<u64>,
+    offset: Option<f64>,
+    end: Option<f64>,
+    index: usize,
+}
+
+impl Joiner {
+    /// Begins the next ride, which started at `start` (milliseconds since the unix epoch).
+    fn next_ride(&mut self, start: Option<u64>) {
+        if self.rides == 0 {
+            self.first_start = start;
+        }
+
+        self.rides += 1;
+        self.start = start;
+        self.offset = None;
+    }
+
+    /// Moves a point of the current ride onto the joined timeline.
+    ///
+    /// Rides are placed according to their start times, keeping the real gaps between them. A
+    /// ride without a start time (or one that would overlap the previous) follows straight on
+    /// from the previous ride's last point.
+    fn place(&mut self, mut point: DataPoint) -> DataPoint {
+        let offset = *self.offset.get_or_insert_with(|| match self.end {
+            None => 0.0,
+            Some(end) => match (self.first_start, self.start) {
+                (Some(first), Some(start)) => (start as i64 - first as i64) as f64 / 1000.0,
+                _ => end,
+            }
+            .max(end - point.time),
+        });
+
+        point.index = self.index;
+        point.time += offset;
+        self.index += 1;
+        self.end = Some(point.time);
+        point
+    }
+}
+
+#[derive(Clone)]
+pub struct DataPoint {
+    pub index: usize,
+    /// Seconds since the start of the ride.
+    pub time: f64,
+
+    pub speed: f32,
+    pub duty_cycle: f32,
+
+    pub motor_current: f32,
+    pub field_weakening: Option<f32>,
+
+    pub current_requested: Option<f32>,
+    pub current_booster: Option<f32>,
+    pub erpm: Option<f32>,
+
+    pub temp_motor: f32,
+    pub temp_mosfet: f32,
+    pub temp_battery: Option<f32>,
+    pub temp_bms: Option<f32>,
+
+    pub batt_voltage: f32,
+    pub batt_current: f32,
+    /// State of charge, in %.
+    pub battery_percent: Option<f32>,
+
+    /// Float package state, e.g. `RIDING`.
+    pub state: Option<String>,
+    /// Float package state as its numeric code.
+    pub state_code: Option<u8>,
+    pub fault_motor: Option<u8>,
+    pub fault_bms: Option<u8>,
+
+    /// Footpad sensor voltages.
+    pub adc1: Option<f32>,
+    pub adc2: Option<f32>,
+
+    /// Angles, in degrees.
+    pub pitch: Option<f32>,
+    pub true_pitch: Option<f32>,
+    pub roll: Option<f32>,
+
+    /// Balance setpoint and the contributions to it, in degrees.
+    pub setpoint: Option<f32>,
+    pub setpoint_atr: Option<f32>,
+    pub setpoint_carve: Option<f32>,
+    pub setpoint_torque_tilt: Option<f32>,
+    pub setpoint_brake_tilt: Option<f32>,
+    pub setpoint_remote: Option<f32>,
+
+    /// Trip distance, in km.
+    pub distance: Option<f32>,
+    pub amp_hours: Option<f32>,
+    pub amp_hours_charged: Option<f32>,
+    pub watt_hours: Option<f32>,
+    pub watt_hours_charged: Option<f32>,
+
+    /// Watts drawn from the battery, negative while regenerating.
+    pub power: Option<f32>,
+    /// Net energy used per distance so far, in Wh/km.
+    pub efficiency: Option<f32>,
+    /// Change in speed, in m/s².
+    pub acceleration: Option<f32>,
+
+    pub position: Option<Position>,
+}
+
+impl Default for DataPoint {
+    fn default() -> Self {
+        DataPoint {
+            index: 0,
+            time: 0.0,
+            speed: f32::NAN,
+            duty_cycle: f32::NAN,
+            motor_current: f32::NAN,
+            field_weakening: None,
+            current_requested: None,
+            current_booster: None,
+            erpm: None,
+            temp_motor: f32::NAN,
+            temp_mosfet: f32::NAN,
+            temp_battery: None,
+            temp_bms: None,
+            batt_voltage: f32::NAN,
+            batt_current: f32::NAN,
+            battery_percent: None,
+            state: None,
+            state_code: None,
+            fault_motor: None,
+            fault_bms: None,
+            adc1: None,
+            adc2: None,
+            pitch: None,
+            true_pitch: None,
+            roll: None,
+            setpoint: None,
+            setpoint_atr: None,
+            setpoint_carve: None,
+            setpoint_torque_tilt: None,
+            setpoint_brake_tilt: None,
+            setpoint_remote: None,
+            distance: None,
+            amp_hours: None,
+            amp_hours_charged: None,
+            watt_hours: None,
+            watt_hours_charged: None,
+            power: None,
+            efficiency: None,
+            acceleration: None,
+            position: None,
+        }
+    }
+}
+
+/// A log format rides can be read from.
+///
+/// Formats are listed in [`FORMATS`], which is where a new one needs adding.
+pub trait LogFormat: Sync {
+    /// Used to pick the format with `--format`.
+    fn name(&self) -> &'static str;
+
+    /// What writes logs in this format.
+    fn description(&self) -> &'static str;
+
+    /// Whether the first bytes of a file look like this format.
+    fn detect(&self, head: &[u8]) -> bool;
+
+    /// The channels which logs in this format can fill in.
+    fn channels(&self) -> &'static [Channel];
+
+    /// Reads a ride, sending it to `sink` as it's parsed.
+    fn parse(&self, rdr: Box<dyn Read + Send>, options: &Options, sink: &mut Sink) -> Result<()>;
+}
+
+impl fmt::Debug for dyn LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Every supported format, in the order they're tried when detecting the format of a file.
+pub const FORMATS: &[&dyn LogFormat] = &[
+    &archive::Zip,
+    &floaty::Floaty,
+    &float_control::FloatControl,
+    &vesc_tool::VescTool,
+    &mapped::Mapped,
+];
+
+/// Finds a format by its name.
+pub fn find_format(name: &str) -> Result<&'static dyn LogFormat> {
+    match FORMATS.iter().find(|format| format.name() == name) {
+        Some(format) => Ok(*format),
+        None => bail!(
+            "unknown format {}, expected one of: {}",
+            name,
+            FORMATS
+                .iter()
+                .map(|format| format.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Guesses the format from the first bytes of a file.
+fn detect(head: &[u8]) -> Option<&'static dyn LogFormat> {
+    FORMATS.iter().find(|format| format.detect(head)).copied()
+}
+
+/// The start of a text file, without any byte order mark or leading whitespace.
+fn head_text(head: &[u8]) -> String {
+    let text = String::from_utf8_lossy(head);
+    text.trim_start_matches('\u{feff}').trim_start().to_string()
+}
+
+/// The first line of a text file, see [`head_text`].
+fn header_line(head: &[u8]) -> String {
+    head_text(head)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Skips detecting the format from the file's contents.
+    pub format: Option<&'static dyn LogFormat>,
+    pub zip_entry: ZipEntry,
+    /// Used to read the input if given, see [`mapped::Mapped`].
+    pub mapping: Option<Mapping>,
+    /// Skip rows that can't be parsed rather than failing.
+    pub lenient: bool,
+}
+
+/// Milliseconds since the unix epoch of the given (proleptic Gregorian) date and time in UTC.
+fn unix_millis(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: f64) -> i64 {
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let seconds = (days * 86400 + hour as i64 * 3600 + minute as i64 * 60) as f64 + second;
+    (seconds * 1000.0).round() as i64
+}
+
+/// How much of the input is looked at to detect its format.
+const HEAD_SIZE: u64 = 64 * 1024;
+
+/// Reads the first [`HEAD_SIZE`] bytes of `rdr`, returning them along with a reader over the whole
+/// input.
+fn peek(mut rdr: Box<dyn Read + Send>) -> Result<(Vec<u8>, Box<dyn Read + Send>)> {
+    let mut head = vec![];
+    rdr.by_ref().take(HEAD_SIZE).read_to_end(&mut head)?;
+    Ok((head.clone(), Box::new(io::Cursor::new(head).chain(rdr))))
+}
+
+/// Opens a ride for streaming.
+///
+/// An `input_file` of `-` reads from stdin, and gzip compressed input is decompressed as it's read.
+pub fn open(input_file: impl AsRef<str>, options: &Options) -> Result<Stream> {
+    let input_file = input_file.as_ref();
+
+    let rdr: Box<dyn Read + Send> = match input_file {
+        "-" => Box::new(io::stdin()),
+        _ => Box::new(File::open(input_file)?),
+    };
+    let (head, rdr) = peek(rdr)?;
+    let (head, rdr) = if head.starts_with(b"\x1f\x8b") {
+        peek(Box::new(MultiGzDecoder::new(rdr)))?
+    } else {
+        (head, rdr)
+    };
+
+    let format = match options
+        .format
+        .or(options.mapping.as_ref().map(|_| &mapped::Mapped as _))
+    {
+        Some(format) => format,
+        None => match detect(&head) {
+            Some(format) => format,
+            None => bail!("Unsupported file format, try passing --format"),
+        },
+    };
+
+    let rdr = Box::new(BufReader::with_capacity(64 * 1024, rdr));
+    let options = options.clone();
+    Stream::spawn(options.lenient, move |sink| {
+        format.parse(rdr, &options, sink)
+    })
+}
+
+/// Opens several rides for streaming as one.
+///
+/// The rides are joined in order of their start times, keeping the real gaps between them. If any
+/// lacks a start time, they're joined in the order given instead.
+pub fn open_all(input_files: &[impl AsRef<str>], options: &Options) -> Result<Stream> {
+    if let [input_file] = input_files {
+        return open(input_file, options);
+    }
+
+    let mut rides = input_files
+        .iter()
+        .map(|input_file| {
+            let input_file = input_file.as_ref().to_string();
+            match open(&input_file, options) {
+                Ok(stream) => Ok((input_file, stream)),
+                Err(e) => bail!("{}: {}", input_file, e),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match rides.iter().find(|(_, stream)| stream.start_time.is_none()) {
+        Some((input_file, _)) => eprintln!(
+            "Warning: {} has no start time, inputs are joined in the order given",
+            input_file
+        ),
+        None => rides.sort_by_key(|(_, stream)| stream.start_time),
+    }
+
+    Stream::spawn(options.lenient, move |sink| {
+        let start_time = rides.first().and_then(|(_, stream)| stream.start_time);
+        let route = rides
+            .iter_mut()
+            .flat_map(|(_, stream)| std::mem::take(&mut stream.route))
+            .collect();
+        sink.header(start_time, route)?;
+
+        for (input_file, stream) in rides {
+            sink.next_ride(stream.start_time);
+            sink.relay(&input_file, stream)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Reads samples up to the first one `done` returns true for, returning those read so they can
+/// still be rendered. Reading stops early at an error, which is the last sample returned.
+///
+/// Only the samples from the last `keep` seconds of those read are held on to, along with the one
+/// before them to interpolate from, so reading far ahead doesn't hold the ride in memory.
+pub fn read_ahead(
+    samples: &mut impl Iterator<Item = Result<DataPoint>>,
+    keep: f64,
+    mut done: impl FnMut(&DataPoint) -> bool,
+) -> VecDeque<Result<DataPoint>> {
+    let mut read = VecDeque::new();
+    for sample in samples.by_ref() {
+        let (stop, time) = match &sample {
+            Ok(point) => (done(point), point.time),
+            Err(_) => (true, f64::NEG_INFINITY),
+        };
+        read.push_back(sample);
+        while matches!(read.get(1), Some(Ok(point)) if point.time < time - keep) {
+            read.pop_front();
+        }
+        if stop {
+            break;
+        }
+    }
+
+    read
+}
+
+/// Reads a whole ride into memory, see [`open`] to stream it instead.
+#[cfg(test)]
+pub fn parse(input_file: impl AsRef<str>, options: &Options) -> Result<Ride> {
+    open(input_file, options)?.into_ride()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gzip() {
+        let data = parse("test_data/fc_metric.csv.gz", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 1);
+        assert_eq!(data.points[0].speed, 10.0);
+
+        let data = parse("test_data/fc_metric.csv.zip.gz", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 1);
+        assert_eq!(data.points[0].speed, 10.0);
+    }
+
+    #[test]
+    fn detect_format() {
+        let detect = |head: &[u8]| detect(head).map(|format| format.name());
+        assert_eq!(detect(b"PK\x03\x04rest"), Some("zip"));
+        assert_eq!(detect(b"\n  {\"id\": \"x\", \"logs\": []}"), Some("floaty"));
+        assert_eq!(
+            detect(b"Time(s),State,Speed(km/h)\n0.1,RIDING,1"),
+            Some("float-control")
+        );
+        assert_eq!(
+            detect(b"ms_today;input_voltage;temp_mos\n1;2;3"),
+            Some("vesc-tool")
+        );
+        assert_eq!(detect(b"{\"other\": 1}"), None);
+        assert_eq!(detect(b"a,b,c\nTime(s)"), None);
+        assert_eq!(detect(b""), None);
+    }
+
+    #[test]
+    fn format_override() {
+        let options = Options {
+            format: Some(find_format("float-control").unwrap()),
+            ..Default::default()
+        };
+        assert!(parse("test_data/floaty.json", &options).is_err());
+        assert_eq!(find_format("floaty").unwrap().name(), "floaty");
+        assert!(find_format("xml").is_err());
+    }
+
+    #[test]
+    fn unix_time() {
+        assert_eq!(unix_millis(1970, 1, 1, 0, 0, 0.0), 0);
+        assert_eq!(unix_millis(2024, 9, 16, 9, 36, 49.45), 1726479409450);
+    }
+
+    #[test]
+    fn stream_points() {
+        let mut stream = open("test_data/floaty.json", &Options::default()).unwrap();
+        assert_eq!(stream.start_time, Some(100));
+        assert_eq!(stream.route.len(), 5);
+        assert_eq!(stream.next().unwrap().unwrap().index, 0);
+        assert_eq!(stream.next().unwrap().unwrap().index, 1);
+        // dropping the rest stops the parser
+        drop(stream);
+
+        let mut stream = open("test_data/fc_truncated.csv", &Options::default()).unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn join_inputs() {
+        let options = Options::default();
+        let data = open_all(
+            &["test_data/floaty_later.json", "test_data/floaty.json"],
+            &options,
+        )
+        .unwrap()
+        .into_ride()
+        .unwrap();
+        assert_eq!(data.start_time, Some(100));
+        assert_eq!(data.route.len(), 10);
+
+        let times = data.points.iter().map(|p| p.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![0.005, 0.015, 0.025, 10.005, 10.015, 10.025]);
+        let indices = data.points.iter().map(|p| p.index).collect::<Vec<_>>();
+        assert_eq!(indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(data.diagnostics.rows_read, 6);
+
+        // without start times, the order given is kept
+        let data = open_all(
+            &["test_data/fc_metric.csv", "test_data/fc_imperial.csv"],
+            &options,
+        )
+        .unwrap()
+        .into_ride()
+        .unwrap();
+        let speeds = data.points.iter().map(|p| p.speed).collect::<Vec<_>>();
+        assert_eq!(speeds, vec![10.0, 16.0934]);
+
+        let error = open_all(
+            &["test_data/fc_metric.csv", "test_data/fc_truncated.csv"],
+            &options,
+        )
+        .unwrap()
+        .into_ride()
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("test_data/fc_truncated.csv: line 4"));
+    }
+
+    #[test]
+    fn lenient() {
+        assert!(parse("test_data/fc_truncated.csv", &Options::default()).is_err());
+
+        let options = Options {
+            lenient: true,
+            ..Default::default()
+        };
+        let data = parse("test_data/fc_truncated.csv", &options).unwrap();
+        assert_eq!(data.points.len(), 2);
+        assert_eq!(data.diagnostics.rows_read, 3);
+        assert_eq!(data.diagnostics.rows_skipped, 1);
+        assert_eq!(data.diagnostics.errors.len(), 1);
+        assert!(data.diagnostics.errors[0].starts_with("line 4: expected 36 fields"));
+
+        let data = parse("test_data/floaty_truncated.json", &options).unwrap();
+        assert_eq!(data.points.len(), 2);
+        assert_eq!(data.diagnostics.rows_skipped, 2);
+        assert!(data.diagnostics.errors[0].starts_with("log 2: invalid type"));
+        assert!(parse("test_data/floaty_truncated.json", &Options::default()).is_err());
+
+        let diagnostics = Diagnostics {
+            rows_read: 20,
+            rows_skipped: 12,
+            errors: vec!["line 2: bad".into(); REPORTED_ERRORS],
+        };
+        assert_eq!(diagnostics.to_string(), "Read 20 rows, skipped 12");
+    }
+}
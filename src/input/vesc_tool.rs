@@ -0,0 +1,211 @@
+use std::io::Read;
+
+use serde_derive::Deserialize;
+
+use super::{header_line, Coordinate, DataPoint, LogFormat, Options, Position, Sink};
+use crate::channel::Channel;
+use crate::err::Result;
+
+/// A row of a VESC Tool realtime log.
+#[derive(Debug, Deserialize)]
+struct VescToolCsv {
+    /// Milliseconds since midnight.
+    #[serde(rename = "ms_today")]
+    ms_today: i64,
+    #[serde(rename = "input_voltage")]
+    input_voltage: f32,
+    #[serde(rename = "temp_mos", alias = "temp_mos_max")]
+    temp_mos: f32,
+    #[serde(rename = "temp_motor")]
+    temp_motor: f32,
+    #[serde(rename = "current_motor")]
+    current_motor: f32,
+    #[serde(rename = "current_in")]
+    current_in: f32,
+    #[serde(rename = "erpm")]
+    erpm: f32,
+    /// Fraction, from 0 to 1.
+    #[serde(rename = "duty_cycle")]
+    duty_cycle: f32,
+    #[serde(rename = "amp_hours_used")]
+    amp_hours_used: Option<f32>,
+    #[serde(rename = "amp_hours_charged")]
+    amp_hours_charged: Option<f32>,
+    #[serde(rename = "watt_hours_used")]
+    watt_hours_used: Option<f32>,
+    #[serde(rename = "watt_hours_charged")]
+    watt_hours_charged: Option<f32>,
+    #[serde(rename = "fault_code")]
+    fault_code: Option<u8>,
+    #[serde(rename = "speed_meters_per_sec")]
+    speed_meters_per_sec: Option<f32>,
+    #[serde(rename = "tacho_abs_meters")]
+    tacho_abs_meters: Option<f32>,
+    /// Radians.
+    #[serde(rename = "roll")]
+    roll: Option<f32>,
+    /// Radians.
+    #[serde(rename = "pitch")]
+    pitch: Option<f32>,
+    #[serde(rename = "gnss_lat")]
+    gnss_lat: Option<f64>,
+    #[serde(rename = "gnss_lon")]
+    gnss_lon: Option<f64>,
+    #[serde(rename = "gnss_alt")]
+    gnss_alt: Option<f32>,
+    /// Metres per second.
+    #[serde(rename = "gnss_gVel")]
+    gnss_ground_velocity: Option<f32>,
+    #[serde(rename = "gnss_hAcc")]
+    gnss_horizontal_accuracy: Option<f32>,
+}
+
+impl VescToolCsv {
+    fn position(&self) -> Option<Position> {
+        let (latitude, longitude) = match (self.gnss_lat, self.gnss_lon) {
+            (Some(lat), Some(lon)) if lat != 0.0 || lon != 0.0 => (lat, lon),
+            _ => return None,
+        };
+
+        Some(Position {
+            coordinate: Coordinate {
+                latitude,
+                longitude,
+            },
+            altitude: self.gnss_alt,
+            speed: self.gnss_ground_velocity.map(|v| v * 3.6),
+            accuracy: self.gnss_horizontal_accuracy,
+        })
+    }
+
+    fn to_data_point(&self, time: f64, index: usize) -> DataPoint {
+        DataPoint {
+            index,
+            time,
+            speed: self
+                .speed_meters_per_sec
+                .map_or(f32::NAN, |v| v.abs() * 3.6),
+            duty_cycle: self.duty_cycle * 100.0,
+            motor_current: self.current_motor,
+            erpm: Some(self.erpm),
+            temp_motor: self.temp_motor,
+            temp_mosfet: self.temp_mos,
+            batt_voltage: self.input_voltage,
+            batt_current: self.current_in,
+            fault_motor: self.fault_code,
+            pitch: self.pitch.map(f32::to_degrees),
+            roll: self.roll.map(f32::to_degrees),
+            distance: self.tacho_abs_meters.map(|m| m / 1000.0),
+            amp_hours: self.amp_hours_used,
+            amp_hours_charged: self.amp_hours_charged,
+            watt_hours: self.watt_hours_used,
+            watt_hours_charged: self.watt_hours_charged,
+            position: self.position(),
+            ..Default::default()
+        }
+    }
+}
+
+fn parse_vesc_tool<R: Read>(rdr: R, sink: &mut Sink) -> Result<()> {
+    const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+    let rdr = csv::ReaderBuilder::new().delimiter(b';').from_reader(rdr);
+    let mut first_ms = None;
+    let mut i = 0;
+    for result in rdr.into_deserialize() {
+        let record: VescToolCsv = match result {
+            Ok(record) => record,
+            Err(e) => {
+                sink.skip_csv(e)?;
+                continue;
+            }
+        };
+
+        // `ms_today` wraps around at midnight
+        let first_ms = *first_ms.get_or_insert(record.ms_today);
+        let elapsed = (record.ms_today - first_ms).rem_euclid(MS_PER_DAY);
+        sink.point(record.to_data_point(elapsed as f64 / 1000.0, i))?;
+        i += 1;
+    }
+
+    Ok(())
+}
+
+/// VESC Tool realtime log CSV.
+pub struct VescTool;
+
+impl LogFormat for VescTool {
+    fn name(&self) -> &'static str {
+        "vesc-tool"
+    }
+
+    fn description(&self) -> &'static str {
+        "VESC Tool realtime log CSV"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        header_line(head)
+            .split(';')
+            .any(|column| column == "ms_today")
+    }
+
+    fn channels(&self) -> &'static [Channel] {
+        &[
+            Channel::Speed,
+            Channel::DutyCycle,
+            Channel::MotorCurrent,
+            Channel::Erpm,
+            Channel::TempMotor,
+            Channel::TempMosfet,
+            Channel::BattVoltage,
+            Channel::BattCurrent,
+            Channel::FaultMotor,
+            Channel::Pitch,
+            Channel::Roll,
+            Channel::Distance,
+            Channel::AmpHours,
+            Channel::AmpHoursCharged,
+            Channel::WattHours,
+            Channel::WattHoursCharged,
+            Channel::Latitude,
+            Channel::Longitude,
+            Channel::Altitude,
+            Channel::GpsSpeed,
+            Channel::GpsAccuracy,
+        ]
+    }
+
+    fn parse(&self, rdr: Box<dyn Read + Send>, _: &Options, sink: &mut Sink) -> Result<()> {
+        parse_vesc_tool(rdr, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::parse;
+
+    #[test]
+    fn vesc_tool() {
+        let data = parse("test_data/vesc_tool.csv", &Options::default()).unwrap();
+        assert_eq!(data.points.len(), 3);
+
+        let times = data.points.iter().map(|p| p.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![0.0, 0.05, 0.1]);
+
+        let point = &data.points[1];
+        assert_eq!(point.batt_voltage, 75.5);
+        assert_eq!(point.batt_current, 3.5);
+        assert_eq!(point.motor_current, 12.0);
+        assert_eq!(point.duty_cycle, 25.0);
+        assert_eq!(point.temp_mosfet, 31.0);
+        assert_eq!(point.erpm, Some(1500.0));
+        assert_eq!(point.speed, 18.0);
+        assert_eq!(point.distance, Some(0.0125));
+        assert_eq!(point.position, None);
+
+        let position = data.points[2].position.as_ref().unwrap();
+        assert_eq!(position.coordinate.latitude, -1.5);
+        assert_eq!(position.speed, Some(18.0));
+    }
+}
@@ -1,7 +1,16 @@
+mod battery;
+mod board;
+mod channel;
 mod cli;
+mod derived;
 mod err;
+mod filter;
 mod input;
+mod outlier;
 mod render;
+mod resample;
+mod sync;
+mod trim;
 
 use std::io::Write;
 use std::process::{Command, Stdio};
@@ -12,8 +21,16 @@ use sdl2::render::{Canvas, TextureCreator};
 use sdl2::ttf::Font;
 use sdl2::video::{Window, WindowContext};
 
+use crate::battery::Pack;
+use crate::board::Speeds;
+use crate::derived::Derived;
 use crate::err::Result;
+use crate::filter::Smoothed;
+use crate::outlier::Outliers;
 use crate::render::*;
+use crate::resample::{Frame, Gaps, Resampler};
+use crate::sync::{Clip, Detector};
+use crate::trim::Trim;
 
 const WIDTH: u32 = 400;
 const HEIGHT: u32 = 960;
@@ -27,7 +44,8 @@ pub struct Context<'a> {
     font_regular: &'a Font<'a, 'a>,
 }
 
-fn render_frame(ctx: &mut Context, point: &DataPoint) -> Result<()> {
+fn render_frame(ctx: &mut Context, frame: &Frame) -> Result<()> {
+    let point = &frame.point;
     ctx.canvas.set_draw_color(Color::RGBA(
         0,
         0,
@@ -87,16 +105,20 @@ fn render_frame(ctx: &mut Context, point: &DataPoint) -> Result<()> {
         vec![
             LabelValue::new(
                 "Voltage (per cell)",
-                &format!("{:.2} V", point.batt_voltage / ctx.args.cell_count as f32),
+                &format!(
+                    "{:.2} V",
+                    point.batt_voltage / ctx.args.cell_count.map_or(f32::NAN, f32::from)
+                ),
             ),
             LabelValue::new("Voltage", &format!("{:.2} V", point.batt_voltage)),
             LabelValue::new("Current", &format!("{:.2} A", point.batt_current)),
+            LabelValue::new(
+                "Charge",
+                &format!("{:.0}%", point.battery_percent.unwrap_or(f32::NAN)),
+            ),
             LabelValue::new(
                 "Wattage",
-                &format!(
-                    "{} W",
-                    (point.batt_voltage * point.batt_current).round() as usize
-                ),
+                &format!("{} W", point.power.unwrap_or(f32::NAN).round() as isize),
             ),
         ],
     )
@@ -104,15 +126,139 @@ fn render_frame(ctx: &mut Context, point: &DataPoint) -> Result<()> {
     .render(ctx, y as f64)?
     .1;
 
+    if frame.in_gap && ctx.args.gaps == Gaps::Pause {
+        Banner::new("Signal lost")
+            .with_color(Color::RED)
+            .render(ctx, HEIGHT as f64 / 2.0)?;
+    }
+
     Ok(())
 }
 
 fn main() -> Result<()> {
-    let args = cli::Args::parse()?;
+    let mut args = cli::Args::parse()?;
+
+    let options = input::Options {
+        format: args.format,
+        zip_entry: args.zip_entry.clone(),
+        mapping: args
+            .mapping
+            .as_ref()
+            .map(input::Mapping::load)
+            .transpose()?,
+        lenient: args.lenient,
+    };
+    let stream = input::open_all(&args.inputs, &options)?;
+
+    // where the video starts in the ride, and how long it lasts
+    let (mut video_start, video_duration) = match &args.sync_video {
+        Some(video) => {
+            let clip = Clip::probe(video)?;
+            let Some(start_time) = stream.start_time else {
+                bail!("the log has no start time to line {} up with", video);
+            };
+            let start = clip.offset(start_time);
+            eprintln!(
+                "{} starts {:.1} s into the ride and lasts {:.1} s",
+                video, start, clip.duration
+            );
+            (
+                Some(start + args.offset.unwrap_or(0.0)),
+                Some(clip.duration),
+            )
+        }
+        None => (args.offset, None),
+    };
+    let start = args
+        .start
+        .map(|start| start.seconds(stream.start_time))
+        .transpose()?;
+    let end = args
+        .end
+        .map(|end| end.seconds(stream.start_time))
+        .transpose()?;
+    if let (Some(start), Some(end)) = (start, end) {
+        if start >= end {
+            bail!("--start must be before --end");
+        }
+    }
+
+    let mut speeds = Speeds::new(args.speed_source);
+    let samples = stream.map(move |point| {
+        let mut point = point?;
+        speeds.apply(&mut point);
+        Ok(point)
+    });
+    let samples = Trim::new(samples, start, end, args.trim_idle);
+    let mut samples: Box<dyn Iterator<Item = Result<DataPoint>>> = if args.keep_outliers {
+        Box::new(samples)
+    } else {
+        Box::new(Outliers::new(samples))
+    };
 
-    let data = input::parse(&args.input)?;
-    if data.is_empty() {
-        bail!("No data points found in input {}", args.input);
+    if args.list_sync_markers {
+        let mut detector = Detector::default();
+        for point in samples {
+            if let Some(marker) = detector.push(&point?) {
+                println!("Found a {}", marker);
+            }
+        }
+        return Ok(());
+    }
+
+    if args.sync_marker {
+        let (marker, read) = sync::find_marker(&mut samples, args.offset.unwrap_or(0.0));
+        let Some(marker) = marker else {
+            // reading may have stopped at an error
+            read.into_iter().collect::<Result<Vec<_>>>()?;
+            bail!("no sync gesture found in the ride, see --list-sync-markers");
+        };
+        eprintln!("Found a {}, starting the video there", marker);
+        video_start = Some(marker.time + args.offset.unwrap_or(0.0));
+        samples = Box::new(read.into_iter().chain(samples));
+    }
+
+    if args.cell_count.is_none() {
+        let (guess, read) = battery::guess_cell_count(&mut samples, args.chemistry);
+        match guess {
+            Some(guess) => {
+                eprintln!("{}", guess);
+                args.cell_count = Some(guess.cells);
+            }
+            None => eprintln!(
+                "Couldn't guess the battery's cell count from its voltage, use --cell-count to show \
+                 the voltage per cell and, if the log doesn't record it, the charge"
+            ),
+        }
+        samples = Box::new(read.into_iter().chain(samples));
+    }
+
+    let pack = args.cell_count.map(|cells| Pack {
+        chemistry: args.chemistry,
+        cells,
+        parallel: args.parallel_count,
+    });
+    let samples = Derived::new(samples).map(|point| {
+        let mut point = point?;
+        if let Some(pack) = &pack {
+            pack.apply(&mut point);
+        }
+        Ok(point)
+    });
+
+    // one point per frame, frame N showing the ride as it was N / rate seconds in
+    let mut points = Resampler::new(
+        Smoothed::new(samples, args.filters.clone()),
+        args.interpolation,
+        args.rate as f64,
+        args.max_gap_seconds as f64,
+        args.gaps,
+    )
+    .with_start(video_start)
+    .with_duration(video_duration)
+    .peekable();
+    if points.peek().is_none() {
+        bail!("No data points found in input {}", args.inputs.join(", "));
     }
 
     let sdl_context = sdl2::init()?;
@@ -186,9 +332,8 @@ fn main() -> Result<()> {
 
     let ffmpeg_stdin = ffmpeg.stdin.as_mut().ok_or("Failed to open ffmpeg stdin")?;
 
-    for point in data.iter() {
-        let duration = point.duration.min(args.max_gap_seconds);
-        let num_frames = (duration * args.rate).round() as usize;
+    for (n, frame) in points.enumerate() {
+        let frame = frame?;
 
         canvas.with_texture_canvas(&mut texture, |texture_canvas| {
             let mut ctx = Context {
@@ -200,17 +345,14 @@ fn main() -> Result<()> {
                 font_regular: &font_regular,
             };
 
-            if let Err(e) = render_frame(&mut ctx, &point) {
-                eprintln!("Error rendering frame {}: {}", point.index, e);
+            if let Err(e) = render_frame(&mut ctx, &frame) {
+                eprintln!("Error rendering frame {}: {}", n, e);
             }
         })?;
 
         canvas.copy(&texture, None, None)?;
 
-        let pixel_data = &canvas.read_pixels(None, pixel_format)?;
-        for _ in 0..num_frames {
-            ffmpeg_stdin.write_all(pixel_data)?;
-        }
+        ffmpeg_stdin.write_all(&canvas.read_pixels(None, pixel_format)?)?;
     }
 
     // Wait for the ffmpeg process to complete
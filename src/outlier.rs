@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::channel::Channel;
+use crate::err::Result;
+use crate::input::DataPoint;
+
+/// Checks for each channel: `(channel, min, max, max_jump)`.
+///
+/// Values outside `min..=max` can't be real, such as a 0 V pack. A value which jumps more than
+/// `max_jump` away from the samples either side, while they agree with each other, is a one sample
+/// glitch rather than a real change.
+const LIMITS: &[(Channel, f64, f64, f64)] = &[
+    (Channel::Speed, -100.0, 100.0, 15.0),
+    (Channel::GpsSpeed, 0.0, 100.0, 15.0),
+    (Channel::DutyCycle, -100.0, 100.0, 50.0),
+    (Channel::MotorCurrent, -400.0, 400.0, f64::INFINITY),
+    (Channel::BattCurrent, -400.0, 400.0, f64::INFINITY),
+    (Channel::BattVoltage, 10.0, 150.0, 20.0),
+    (Channel::TempMotor, -40.0, 200.0, 20.0),
+    (Channel::TempMosfet, -40.0, 200.0, 20.0),
+    (Channel::TempBattery, -40.0, 200.0, 20.0),
+    (Channel::TempBms, -40.0, 200.0, 20.0),
+    (Channel::Altitude, -500.0, 9000.0, 100.0),
+];
+
+/// How many samples ahead to look for a good value to interpolate towards.
+const MAX_RUN: usize = 5;
+
+/// How many values of each channel were replaced.
+#[derive(Debug, Default)]
+pub struct Replaced(Vec<(Channel, usize)>);
+
+impl Replaced {
+    fn add(&mut self, channel: Channel) {
+        match self.0.iter_mut().find(|(c, _)| *c == channel) {
+            Some((_, count)) => *count += 1,
+            None => self.0.push((channel, 1)),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.0.iter().map(|(_, count)| count).sum()
+    }
+}
+
+impl fmt::Display for Replaced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Replaced {} outlying values", self.total())?;
+        for (i, (channel, count)) in self.0.iter().enumerate() {
+            write!(
+                f,
+                "{} {} {}",
+                if i == 0 { ":" } else { "," },
+                channel,
+                count
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces impossible values, and single sample spikes, with values interpolated from the good
+/// samples either side. How many were replaced is printed once the ride has been read.
+pub struct Outliers<I> {
+    samples: I,
+    /// The last sample sent, with its outliers replaced.
+    previous: Option<DataPoint>,
+    /// The next sample to be sent, followed by those read ahead.
+    window: VecDeque<DataPoint>,
+    error: Option<Box<dyn std::error::Error>>,
+    exhausted: bool,
+    replaced: Replaced,
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Outliers<I> {
+    pub fn new(samples: I) -> Self {
+        Outliers {
+            samples,
+            previous: None,
+            window: VecDeque::new(),
+            error: None,
+            exhausted: false,
+            replaced: Replaced::default(),
+        }
+    }
+
+    fn reject(&mut self) -> DataPoint {
+        let mut point = self.window[0].clone();
+        for &(channel, min, max, max_jump) in LIMITS {
+            let Some(value) = channel.get(&point) else {
+                continue;
+            };
+
+            let good = |point: &DataPoint| {
+                channel
+                    .get(point)
+                    .filter(|value| (min..=max).contains(value))
+                    .map(|value| (point.time, value))
+            };
+            let before = self.previous.as_ref().and_then(good);
+            let after = self.window.iter().skip(1).find_map(good);
+
+            let outlier = if !(min..=max).contains(&value) {
+                true
+            } else {
+                match (before, self.window.get(1).and_then(good)) {
+                    (Some((_, y0)), Some((_, y2))) => {
+                        (value - y0).abs() > max_jump
+                            && (value - y2).abs() > max_jump
+                            && (y2 - y0).abs() <= max_jump
+                            && (value - y0).signum() == (value - y2).signum()
+                    }
+                    _ => false,
+                }
+            };
+            if !outlier {
+                continue;
+            }
+
+            let value = match (before, after) {
+                (Some((t0, y0)), Some((t2, y2))) if t2 > t0 => {
+                    y0 + (y2 - y0) * ((point.time - t0) / (t2 - t0)).clamp(0.0, 1.0)
+                }
+                (Some((_, y)), _) | (None, Some((_, y))) => y,
+                // nothing to replace it with
+                (None, None) => continue,
+            };
+            channel.set(&mut point, value);
+            self.replaced.add(channel);
+        }
+
+        point
+    }
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Iterator for Outliers<I> {
+    type Item = Result<DataPoint>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.exhausted && self.window.len() <= MAX_RUN {
+            match self.samples.next() {
+                Some(Ok(point)) => self.window.push_back(point),
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    self.exhausted = true;
+                }
+                None => {
+                    self.exhausted = true;
+                    if self.replaced.total() > 0 {
+                        eprintln!("{}", self.replaced);
+                    }
+                }
+            }
+        }
+
+        if self.window.is_empty() {
+            return self.error.take().map(Err);
+        }
+
+        let point = self.reject();
+        self.window.pop_front();
+        self.previous = Some(point.clone());
+        Some(Ok(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(values: &[f32], channel: Channel) -> Vec<Result<DataPoint>> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let mut point = DataPoint {
+                    time: i as f64,
+                    ..Default::default()
+                };
+                channel.set(&mut point, *value as f64);
+                Ok(point)
+            })
+            .collect()
+    }
+
+    fn reject(values: &[f32], channel: Channel) -> (Vec<f64>, usize) {
+        let mut outliers = Outliers::new(points(values, channel).into_iter());
+        let values = outliers
+            .by_ref()
+            .map(|point| channel.get(&point.unwrap()).unwrap())
+            .collect();
+        (values, outliers.replaced.total())
+    }
+
+    #[test]
+    fn impossible() {
+        assert_eq!(
+            reject(&[80.0, 0.0, 0.0, 83.0], Channel::BattVoltage),
+            (vec![80.0, 81.0, 82.0, 83.0], 2)
+        );
+        assert_eq!(
+            reject(&[30.0, 999.0, 30.0], Channel::TempMotor),
+            (vec![30.0, 30.0, 30.0], 1)
+        );
+
+        // held at the ends of the ride
+        assert_eq!(
+            reject(&[0.0, 80.0, 0.0], Channel::BattVoltage),
+            (vec![80.0, 80.0, 80.0], 2)
+        );
+        // and left alone when there's nothing to replace them with
+        assert_eq!(
+            reject(&[0.0, 0.0], Channel::BattVoltage),
+            (vec![0.0, 0.0], 0)
+        );
+    }
+
+    #[test]
+    fn spikes() {
+        assert_eq!(
+            reject(&[10.0, 11.0, 60.0, 13.0, 14.0], Channel::Speed),
+            (vec![10.0, 11.0, 12.0, 13.0, 14.0], 1)
+        );
+
+        // real changes are kept, however sudden
+        let step = [10.0, 40.0, 41.0, 42.0];
+        assert_eq!(
+            reject(&step, Channel::Speed),
+            (step.map(|v| v as f64).to_vec(), 0)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut samples = points(&[80.0, 0.0], Channel::BattVoltage);
+        samples.push(Err("bad row".into()));
+        let points = Outliers::new(samples.into_iter()).collect::<Vec<_>>();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[1].as_ref().unwrap().batt_voltage, 80.0);
+        assert!(points[2].is_err());
+    }
+
+    #[test]
+    fn report() {
+        let mut replaced = Replaced::default();
+        replaced.add(Channel::BattVoltage);
+        replaced.add(Channel::TempMotor);
+        replaced.add(Channel::BattVoltage);
+        assert_eq!(
+            replaced.to_string(),
+            "Replaced 3 outlying values: batt_voltage 2, temp_motor 1"
+        );
+    }
+}
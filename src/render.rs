@@ -235,6 +235,43 @@ impl Speedo {
     }
 }
 
+/// Text across the middle of the video, over a dark band.
+pub struct Banner {
+    text: String,
+    color: Color,
+}
+
+impl Banner {
+    pub fn new(text: &str) -> Self {
+        Banner {
+            text: String::from(text),
+            color: Color::WHITE,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn render(&self, ctx: &mut Context, y: f64) -> Result<(u32, u32)> {
+        let height = 60.0;
+        ctx.canvas.box_(
+            0,
+            (y - height / 2.0) as i16,
+            WIDTH as i16,
+            (y + height / 2.0) as i16,
+            Color::RGBA(0, 0, 0, 200),
+        )?;
+        Text::new(&self.text)
+            .with_color(self.color)
+            .with_alignment(TextAlignment::Center)
+            .render_with_font(ctx, WIDTH as f64 / 2.0, y, ctx.font_title)?;
+
+        Ok((WIDTH, height as u32))
+    }
+}
+
 pub struct TextTitle {
     title: String,
     color: Color,
@@ -0,0 +1,509 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use crate::channel::Channel;
+use crate::err::Result;
+use crate::input::DataPoint;
+
+/// How values are estimated between two samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    /// Each sample is held until the next.
+    None,
+    Linear,
+    /// A cubic curve through the samples either side, which eases in and out of changes.
+    Cubic,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Interpolation::None),
+            "linear" => Ok(Interpolation::Linear),
+            "cubic" => Ok(Interpolation::Cubic),
+            _ => Err(format!(
+                "unknown interpolation {}, expected one of: none, linear, cubic",
+                s
+            )),
+        }
+    }
+}
+
+/// What's done with gaps in logging longer than the maximum gap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gaps {
+    /// Kept at their full length, so the video stays in time with the ride.
+    Keep,
+    /// Removed, jumping straight to the sample after.
+    Cut,
+    /// Shortened to the maximum gap.
+    Clamp,
+    /// Shortened to the maximum gap, showing that logging had paused.
+    Pause,
+}
+
+impl FromStr for Gaps {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Gaps::Keep),
+            "cut" => Ok(Gaps::Cut),
+            "clamp" => Ok(Gaps::Clamp),
+            "pause" => Ok(Gaps::Pause),
+            _ => Err(format!(
+                "unknown gap handling {}, expected one of: keep, cut, clamp, pause",
+                s
+            )),
+        }
+    }
+}
+
+/// The point shown by a frame of the video.
+pub struct Frame {
+    pub point: DataPoint,
+    /// Whether the frame falls in a gap in logging, or before or after the ride, so shows the
+    /// nearest sample held.
+    pub in_gap: bool,
+}
+
+/// A sample placed on the video's timeline.
+struct Sample {
+    /// Seconds since the start of the video.
+    time: f64,
+    /// Whether the gap before this sample was shortened, in which case the previous sample is held
+    /// rather than interpolated from.
+    after_gap: bool,
+    point: DataPoint,
+}
+
+/// Turns a ride's samples into one point for each frame of the video.
+///
+/// The video starts at the first sample, or at a set time in the ride, and gaps between samples
+/// longer than `max_gap` seconds are kept, cut or shortened to `max_gap`. The points keep the ride's
+/// times, so they skip forward over gaps. How much time was removed is printed once the ride has
+/// been read, or the video has reached its duration.
+///
+/// Frame N is taken from the ride N / rate seconds in, rather than by counting frames per sample,
+/// so rounding can't build up over a long ride. The video ends on the frame showing the last
+/// sample, making it as long as the ride to within a frame, unless it's given a duration. Frames
+/// before the ride starts or after it ends hold the first or last sample.
+pub struct Resampler<I> {
+    samples: I,
+    interpolation: Interpolation,
+    rate: f64,
+    max_gap: f64,
+    gaps: Gaps,
+    /// Seconds into the ride the video starts at, and how long it lasts.
+    start: Option<f64>,
+    duration: Option<f64>,
+    /// The samples either side of the current frame.
+    window: VecDeque<Sample>,
+    /// Seconds of the ride left out of the video so far, from its start and from gaps.
+    removed: f64,
+    /// How many gaps there were, their total length, and how much of them was left out.
+    gap_count: usize,
+    gap_time: f64,
+    gap_removed: f64,
+    frame: u64,
+    exhausted: bool,
+    /// Whether the gaps have been reported, once the video has ended.
+    reported: bool,
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Resampler<I> {
+    pub fn new(
+        samples: I,
+        interpolation: Interpolation,
+        rate: f64,
+        max_gap: f64,
+        gaps: Gaps,
+    ) -> Self {
+        Resampler {
+            samples,
+            interpolation,
+            rate,
+            max_gap,
+            gaps,
+            start: None,
+            duration: None,
+            window: VecDeque::new(),
+            removed: 0.0,
+            gap_count: 0,
+            gap_time: 0.0,
+            gap_removed: 0.0,
+            frame: 0,
+            exhausted: false,
+            reported: false,
+        }
+    }
+
+    /// Starts the video `start` seconds into the ride, rather than at the first sample.
+    pub fn with_start(mut self, start: Option<f64>) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Makes the video last `duration` seconds, rather than until the last sample.
+    pub fn with_duration(mut self, duration: Option<f64>) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Prints how much time was in gaps, and how much of it was left out, the first time it's
+    /// called.
+    fn report_gaps(&mut self) {
+        if self.gap_count > 0 && !self.reported {
+            eprintln!(
+                "Found {} gaps in logging totalling {:.1} s, {:.1} s of which was removed from the \
+                 video",
+                self.gap_count, self.gap_time, self.gap_removed
+            );
+        }
+        self.reported = true;
+    }
+
+    fn push(&mut self, point: DataPoint) {
+        let mut after_gap = false;
+        let time = match self.window.back() {
+            None => {
+                self.removed = self.start.unwrap_or(point.time);
+                point.time - self.removed
+            }
+            Some(last) => {
+                let time = (point.time - self.removed).max(last.time);
+                let gap = time - last.time;
+                if gap > self.max_gap {
+                    let kept = match self.gaps {
+                        Gaps::Keep => gap,
+                        Gaps::Cut => 0.0,
+                        Gaps::Clamp | Gaps::Pause => self.max_gap,
+                    };
+                    self.removed += gap - kept;
+                    self.gap_count += 1;
+                    self.gap_time += gap;
+                    self.gap_removed += gap - kept;
+                    after_gap = true;
+                    last.time + kept
+                } else {
+                    time
+                }
+            }
+        };
+
+        self.window.push_back(Sample {
+            time,
+            after_gap,
+            point,
+        });
+    }
+
+    /// Holds the sample's values at `time`.
+    fn hold(sample: &Sample, time: f64) -> DataPoint {
+        let mut point = sample.point.clone();
+        point.time = sample.point.time + (time - sample.time);
+        point
+    }
+
+    /// Estimates the point at `time` between `window[i]` and `window[i + 1]`.
+    fn interpolate(&self, i: usize, time: f64) -> DataPoint {
+        let s1 = &self.window[i];
+        let mut point = Self::hold(s1, time);
+
+        let Some(s2) = self.window.get(i + 1).filter(|s2| !s2.after_gap) else {
+            return point;
+        };
+        if self.interpolation == Interpolation::None || s2.time <= s1.time {
+            return point;
+        }
+
+        // the outer samples shape cubic curves, where they're close enough to be related
+        let s0 = i
+            .checked_sub(1)
+            .map(|i| &self.window[i])
+            .filter(|_| !s1.after_gap);
+        let s3 = self.window.get(i + 2).filter(|s3| !s3.after_gap);
+
+        let f = (time - s1.time) / (s2.time - s1.time);
+        for channel in Channel::ALL.iter().filter(|c| !c.is_discrete()) {
+            let (Some(y1), Some(y2)) = (channel.get(&s1.point), channel.get(&s2.point)) else {
+                continue;
+            };
+
+            let value = match (self.interpolation, s0, s3) {
+                (Interpolation::Cubic, Some(s0), Some(s3)) => {
+                    match (channel.get(&s0.point), channel.get(&s3.point)) {
+                        (Some(y0), Some(y3)) => {
+                            let dt = s2.time - s1.time;
+                            // tangents from the neighbouring samples, scaled to this interval
+                            let m1 = (y2 - y0) / (s2.time - s0.time) * dt;
+                            let m2 = (y3 - y1) / (s3.time - s1.time) * dt;
+                            hermite(y1, y2, m1, m2, f)
+                        }
+                        _ => y1 + (y2 - y1) * f,
+                    }
+                }
+                _ => y1 + (y2 - y1) * f,
+            };
+            channel.set(&mut point, value);
+        }
+
+        point
+    }
+}
+
+/// Cubic Hermite spline between `y1` and `y2` with tangents `m1` and `m2`, at `f` from 0 to 1.
+fn hermite(y1: f64, y2: f64, m1: f64, m2: f64, f: f64) -> f64 {
+    let f2 = f * f;
+    let f3 = f2 * f;
+    (2.0 * f3 - 3.0 * f2 + 1.0) * y1
+        + (f3 - 2.0 * f2 + f) * m1
+        + (-2.0 * f3 + 3.0 * f2) * y2
+        + (f3 - f2) * m2
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Iterator for Resampler<I> {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let time = self.frame as f64 / self.rate;
+        if self.duration.is_some_and(|duration| time >= duration) {
+            self.report_gaps();
+            return None;
+        }
+
+        // read until there are two samples after this frame, for cubic curves
+        while !self.exhausted && self.window.iter().filter(|s| s.time > time).count() < 2 {
+            match self.samples.next() {
+                Some(Ok(point)) => self.push(point),
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.exhausted = true;
+                    self.report_gaps();
+                }
+            }
+        }
+
+        // keep one sample before the one this frame starts from
+        let current = |window: &VecDeque<Sample>| window.iter().rposition(|s| s.time <= time);
+        while current(&self.window).is_some_and(|i| i > 1) {
+            self.window.pop_front();
+        }
+
+        let Some(i) = current(&self.window) else {
+            // before the first sample
+            let first = self.window.front()?;
+            self.frame += 1;
+            return Some(Ok(Frame {
+                point: Self::hold(first, time),
+                in_gap: true,
+            }));
+        };
+        if i + 1 == self.window.len() && time > self.window[i].time {
+            // past the last sample, which is held until the video ends
+            self.duration?;
+            self.frame += 1;
+            return Some(Ok(Frame {
+                point: Self::hold(&self.window[i], time),
+                in_gap: true,
+            }));
+        }
+
+        let in_gap =
+            time > self.window[i].time && self.window.get(i + 1).is_some_and(|next| next.after_gap);
+        self.frame += 1;
+        Some(Ok(Frame {
+            point: self.interpolate(i, time),
+            in_gap,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(points: &[(f64, f32, u8)]) -> impl Iterator<Item = Result<DataPoint>> {
+        points
+            .iter()
+            .enumerate()
+            .map(|(index, (time, speed, state))| {
+                Ok(DataPoint {
+                    index,
+                    time: *time,
+                    speed: *speed,
+                    state_code: Some(*state),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn frames(points: &[(f64, f32, u8)], interpolation: Interpolation, gaps: Gaps) -> Vec<Frame> {
+        Resampler::new(samples(points), interpolation, 4.0, 2.0, gaps)
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    fn resample(points: &[(f64, f32, u8)], interpolation: Interpolation) -> Vec<DataPoint> {
+        frames(points, interpolation, Gaps::Clamp)
+            .into_iter()
+            .map(|frame| frame.point)
+            .collect()
+    }
+
+    #[test]
+    fn linear() {
+        let points = resample(&[(0.5, 10.0, 1), (1.5, 20.0, 2)], Interpolation::Linear);
+        let speeds = points.iter().map(|p| p.speed).collect::<Vec<_>>();
+        assert_eq!(speeds, vec![10.0, 12.5, 15.0, 17.5, 20.0]);
+        let times = points.iter().map(|p| p.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![0.5, 0.75, 1.0, 1.25, 1.5]);
+
+        // discrete channels are held
+        let states = points.iter().map(|p| p.state_code).collect::<Vec<_>>();
+        assert_eq!(states, vec![Some(1), Some(1), Some(1), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn hold() {
+        let points = resample(&[(0.0, 10.0, 1), (1.0, 20.0, 1)], Interpolation::None);
+        let speeds = points.iter().map(|p| p.speed).collect::<Vec<_>>();
+        assert_eq!(speeds, vec![10.0, 10.0, 10.0, 10.0, 20.0]);
+
+        // missing values aren't interpolated
+        let points = resample(&[(0.0, f32::NAN, 1), (1.0, 20.0, 1)], Interpolation::Linear);
+        assert!(points[2].speed.is_nan());
+    }
+
+    #[test]
+    fn gaps() {
+        let points = resample(
+            &[(0.0, 10.0, 1), (0.5, 20.0, 1), (10.5, 30.0, 1)],
+            Interpolation::Linear,
+        );
+        let speeds = points.iter().map(|p| p.speed).collect::<Vec<_>>();
+        assert_eq!(
+            speeds,
+            vec![10.0, 15.0, 20.0, 20.0, 20.0, 20.0, 20.0, 20.0, 20.0, 20.0, 30.0]
+        );
+        assert_eq!(points[10].time, 10.5);
+    }
+
+    #[test]
+    fn gap_handling() {
+        let points = [(0.0, 10.0, 1), (0.5, 20.0, 1), (10.5, 30.0, 1)];
+
+        let clamped = frames(&points, Interpolation::Linear, Gaps::Pause);
+        assert_eq!(clamped.len(), 11);
+        let in_gap = clamped.iter().map(|f| f.in_gap).collect::<Vec<_>>();
+        assert_eq!(in_gap, [&[false; 3][..], &[true; 7], &[false]].concat());
+
+        let kept = frames(&points, Interpolation::Linear, Gaps::Keep);
+        assert_eq!(kept.len(), 43);
+        assert_eq!(kept[42].point.speed, 30.0);
+        assert!(kept[41].in_gap);
+
+        let cut = frames(&points, Interpolation::Linear, Gaps::Cut);
+        let speeds = cut.iter().map(|f| f.point.speed).collect::<Vec<_>>();
+        assert_eq!(speeds, vec![10.0, 15.0, 30.0]);
+        assert!(cut.iter().all(|f| !f.in_gap));
+    }
+
+    #[test]
+    fn start_and_duration() {
+        let points = samples(&[(10.0, 10.0, 1), (11.0, 20.0, 1)]);
+        let frames = Resampler::new(points, Interpolation::Linear, 4.0, 2.0, Gaps::Clamp)
+            .with_start(Some(9.0))
+            .with_duration(Some(3.0))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let speeds = frames.iter().map(|f| f.point.speed).collect::<Vec<_>>();
+        assert_eq!(
+            speeds,
+            vec![10.0, 10.0, 10.0, 10.0, 10.0, 12.5, 15.0, 17.5, 20.0, 20.0, 20.0, 20.0]
+        );
+        let in_gap = frames.iter().map(|f| f.in_gap).collect::<Vec<_>>();
+        assert_eq!(in_gap, [&[true; 4][..], &[false; 5], &[true; 3]].concat());
+        assert_eq!(frames[0].point.time, 9.0);
+        assert_eq!(frames[11].point.time, 11.75);
+
+        // starting part way through the ride
+        let points = samples(&[(0.0, 10.0, 1), (1.0, 20.0, 1), (2.0, 30.0, 1)]);
+        let frames = Resampler::new(points, Interpolation::Linear, 4.0, 2.0, Gaps::Clamp)
+            .with_start(Some(1.5))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let speeds = frames.iter().map(|f| f.point.speed).collect::<Vec<_>>();
+        assert_eq!(speeds, vec![25.0, 27.5, 30.0]);
+    }
+
+    #[test]
+    fn synced_gap() {
+        // lined up with footage starting 9 s into the ride, with a gap in logging after that
+        let points = samples(&[
+            (10.0, 10.0, 1),
+            (11.0, 20.0, 1),
+            (20.0, 30.0, 1),
+            (21.0, 40.0, 1),
+        ]);
+        let frames = Resampler::new(points, Interpolation::Linear, 4.0, 2.0, Gaps::Keep)
+            .with_start(Some(9.0))
+            .with_duration(Some(15.0))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(frames.len(), 60);
+        for (n, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.point.time, 9.0 + n as f64 / 4.0);
+        }
+        assert_eq!(frames[44].point.speed, 30.0);
+        assert_eq!(frames[48].point.speed, 40.0);
+    }
+
+    #[test]
+    fn no_drift() {
+        // an hour of samples at an uneven ~7.7 Hz, each lasting a fraction of a frame too long to
+        // round evenly
+        let points = (0..27_700)
+            .map(|i| (1.25 + i as f64 * 0.13, 10.0, 1))
+            .collect::<Vec<_>>();
+        let ride = points.last().unwrap().0 - points[0].0;
+
+        let frames = Resampler::new(
+            samples(&points),
+            Interpolation::None,
+            30.0,
+            2.0,
+            Gaps::Clamp,
+        )
+        .map(|frame| frame.map(|frame| frame.point))
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        assert_eq!(frames.len(), (ride * 30.0).floor() as usize + 1);
+        for (n, frame) in frames.iter().enumerate().step_by(997) {
+            assert!((frame.time - (1.25 + n as f64 / 30.0)).abs() < 1e-9);
+            // showing the sample in effect at that time
+            let sample = 1.25 + frame.index as f64 * 0.13;
+            assert!(sample <= frame.time + 1e-9 && frame.time < sample + 0.13);
+        }
+    }
+
+    #[test]
+    fn cubic() {
+        // samples of y = t², which cubic curves follow more closely than straight lines
+        let points = [0.0, 1.0, 2.0, 3.0].map(|t| (t, (t * t) as f32, 1));
+        let linear = resample(&points, Interpolation::Linear);
+        let cubic = resample(&points, Interpolation::Cubic);
+        assert_eq!(linear.len(), 13);
+        assert_eq!(cubic.len(), 13);
+
+        // between t = 1 and t = 2
+        let exact = 1.5f32 * 1.5;
+        assert!((cubic[6].speed - exact).abs() < (linear[6].speed - exact).abs());
+        assert_eq!(cubic[4].speed, 1.0);
+        assert_eq!(cubic[8].speed, 4.0);
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::process::Command;
+
+use crate::bail;
+use crate::channel::Channel;
+use crate::err::Result;
+use crate::input::{parse_datetime, read_ahead, DataPoint};
+
+/// Footpad sensor voltages at which a half of the pad is taken to be pressed, and released.
+const ADC_PRESSED: f64 = 2.5;
+const ADC_RELEASED: f64 = 2.0;
+
+/// Roll angles (in degrees) at which the board is taken to be rolled sharply, and back.
+const ROLL_SPIKE: f64 = 30.0;
+const ROLL_RETURN: f64 = 20.0;
+
+/// Longest a tap or roll spike lasts, and longest between the starts of the two taps of a double
+/// tap, in seconds.
+const MAX_PULSE: f64 = 0.5;
+const MAX_INTERVAL: f64 = 1.0;
+
+/// Speed (in km/h) above which the board is being ridden, so any taps are just riding.
+const MOVING_SPEED: f32 = 2.0;
+
+/// When a video clip was recorded, as read from its metadata.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clip {
+    /// Wall clock time recording started at, in milliseconds since the unix epoch.
+    pub start_time: i64,
+    /// Length of the clip, in seconds.
+    pub duration: f64,
+}
+
+impl Clip {
+    /// Reads the clip's creation time and duration with ffprobe.
+    pub fn probe(path: &str) -> Result<Clip> {
+        let output = Command::new("ffprobe")
+            .args(["-v", "error"])
+            .args(["-show_entries", "format=duration:format_tags=creation_time"])
+            .args(["-of", "default=noprint_wrappers=1"])
+            .arg(path)
+            .output()
+            .map_err(|e| format!("failed to run ffprobe: {}", e))?;
+        if !output.status.success() {
+            bail!(
+                "ffprobe failed to read {}: {}",
+                path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        match parse_probe(&String::from_utf8_lossy(&output.stdout)) {
+            Ok(clip) => Ok(clip),
+            Err(e) => bail!("{}: {}", path, e),
+        }
+    }
+
+    /// Seconds into a ride which started at `ride_start_time` that the clip starts at.
+    pub fn offset(&self, ride_start_time: u64) -> f64 {
+        (self.start_time - ride_start_time as i64) as f64 / 1000.0
+    }
+}
+
+/// Parses ffprobe's `key=value` lines, e.g. `duration=12.5` and
+/// `TAG:creation_time=2024-09-16T09:36:49.000000Z`.
+fn parse_probe(output: &str) -> Result<Clip> {
+    let mut start_time = None;
+    let mut duration = None;
+    for line in output.lines() {
+        match line.trim().split_once('=') {
+            Some(("duration", value)) => duration = value.parse::<f64>().ok(),
+            Some(("TAG:creation_time", value)) => start_time = parse_datetime(value),
+            _ => {}
+        }
+    }
+
+    match (start_time, duration) {
+        (Some(start_time), Some(duration)) => Ok(Clip {
+            start_time,
+            duration,
+        }),
+        (None, _) => bail!("the video has no creation time"),
+        (_, None) => bail!("the video has no duration"),
+    }
+}
+
+/// A gesture made on the board to mark a moment, to be lined up with the same moment on camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    /// A quick double tap on one half of the footpad, pressing or releasing it.
+    DoubleTap(Channel),
+    /// A sharp roll of the board and back.
+    RollSpike,
+}
+
+/// A sync gesture found in the ride.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Marker {
+    pub gesture: Gesture,
+    /// Seconds since the start of the ride the gesture started at.
+    pub time: f64,
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.gesture {
+            Gesture::DoubleTap(channel) => write!(f, "double tap on {}", channel)?,
+            Gesture::RollSpike => write!(f, "roll spike")?,
+        }
+        write!(f, " at {:.2} s", self.time)
+    }
+}
+
+/// The last few times a value crossed over or back under its thresholds.
+struct Edges {
+    over: f64,
+    under: f64,
+    high: Option<bool>,
+    /// Times of the crossings, and whether each went over.
+    edges: VecDeque<(f64, bool)>,
+}
+
+impl Edges {
+    fn new(over: f64, under: f64) -> Self {
+        Edges {
+            over,
+            under,
+            high: None,
+            edges: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, time: f64, value: Option<f64>) {
+        let Some(value) = value else {
+            return;
+        };
+
+        let high = match self.high {
+            Some(true) => value >= self.under,
+            _ => value >= self.over,
+        };
+        if self.high.is_some_and(|was| was != high) {
+            self.edges.push_back((time, high));
+            if self.edges.len() > 4 {
+                self.edges.pop_front();
+            }
+        }
+        self.high = Some(high);
+    }
+}
+
+/// Finds sync gestures made while the board is stopped.
+pub struct Detector {
+    adc1: Edges,
+    adc2: Edges,
+    roll: Edges,
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Detector {
+            adc1: Edges::new(ADC_PRESSED, ADC_RELEASED),
+            adc2: Edges::new(ADC_PRESSED, ADC_RELEASED),
+            roll: Edges::new(ROLL_SPIKE, ROLL_RETURN),
+        }
+    }
+}
+
+impl Detector {
+    /// Returns the gesture ending at this point, if there is one.
+    pub fn push(&mut self, point: &DataPoint) -> Option<Marker> {
+        if point.speed.abs() >= MOVING_SPEED {
+            *self = Detector::default();
+            return None;
+        }
+
+        for (channel, edges) in [
+            (Channel::Adc1, &mut self.adc1),
+            (Channel::Adc2, &mut self.adc2),
+        ] {
+            edges.push(point.time, channel.get(point));
+            // two short pulses close together
+            if let [(t0, _), (t1, _), (t2, _), (t3, _)] = *edges.edges.make_contiguous() {
+                if t1 - t0 <= MAX_PULSE && t3 - t2 <= MAX_PULSE && t2 - t0 <= MAX_INTERVAL {
+                    edges.edges.clear();
+                    return Some(Marker {
+                        gesture: Gesture::DoubleTap(channel),
+                        time: t0,
+                    });
+                }
+            }
+        }
+
+        self.roll
+            .push(point.time, point.roll.map(|roll| roll.abs() as f64));
+        // rolled over and back quickly
+        if let [.., (t0, true), (t1, false)] = *self.roll.edges.make_contiguous() {
+            if t1 - t0 <= MAX_PULSE {
+                self.roll.edges.clear();
+                return Some(Marker {
+                    gesture: Gesture::RollSpike,
+                    time: t0,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+/// Reads the ride up to the first sync gesture, see [`read_ahead`]. Only the samples which may be
+/// rendered, when the video starts `offset` seconds from the gesture, are returned.
+pub fn find_marker(
+    samples: &mut impl Iterator<Item = Result<DataPoint>>,
+    offset: f64,
+) -> (Option<Marker>, VecDeque<Result<DataPoint>>) {
+    // gestures are found at their end, which is at most this long after their start
+    let keep = MAX_INTERVAL + MAX_PULSE + (-offset).max(0.0);
+    let mut detector = Detector::default();
+    let mut marker = None;
+    let read = read_ahead(samples, keep, |point| {
+        marker = detector.push(point);
+        marker.is_some()
+    });
+
+    (marker, read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_output() {
+        let clip =
+            parse_probe("duration=12.500000\nTAG:creation_time=1970-01-01T00:01:00.000000Z\n")
+                .unwrap();
+        assert_eq!(
+            clip,
+            Clip {
+                start_time: 60_000,
+                duration: 12.5
+            }
+        );
+        assert_eq!(clip.offset(15_000), 45.0);
+        assert_eq!(clip.offset(90_000), -30.0);
+
+        assert!(parse_probe("duration=12.5\n").is_err());
+        assert!(parse_probe("duration=N/A\nTAG:creation_time=1970-01-01T00:01:00Z\n").is_err());
+    }
+
+    /// Stationary points at 20 Hz for `seconds`, filled in by `point` from their time.
+    fn points(seconds: f64, point: impl Fn(f64) -> DataPoint) -> Vec<DataPoint> {
+        (0..(seconds * 20.0) as usize)
+            .map(|i| {
+                let time = i as f64 / 20.0;
+                DataPoint {
+                    time,
+                    speed: 0.0,
+                    ..point(time)
+                }
+            })
+            .collect()
+    }
+
+    fn markers(points: &[DataPoint]) -> Vec<Marker> {
+        let mut detector = Detector::default();
+        points
+            .iter()
+            .filter_map(|point| detector.push(point))
+            .collect()
+    }
+
+    fn adc2(pressed: impl Fn(f64) -> bool) -> impl Fn(f64) -> DataPoint {
+        move |time| DataPoint {
+            adc1: Some(0.1),
+            adc2: Some(if pressed(time) { 3.0 } else { 0.1 }),
+            ..Default::default()
+        }
+    }
+
+    fn double_tap(t: f64) -> bool {
+        (1.0..1.2).contains(&t) || (1.5..1.7).contains(&t)
+    }
+
+    #[test]
+    fn footpad_taps() {
+        assert_eq!(
+            markers(&points(3.0, adc2(double_tap))),
+            vec![Marker {
+                gesture: Gesture::DoubleTap(Channel::Adc2),
+                time: 1.0
+            }]
+        );
+
+        // releasing the pad twice while standing on it
+        assert_eq!(markers(&points(3.0, adc2(|t| !double_tap(t)))).len(), 1);
+
+        // a single tap, taps too far apart, and a long press
+        let single = |t: f64| (1.0..1.2).contains(&t);
+        assert!(markers(&points(3.0, adc2(single))).is_empty());
+        let slow = |t: f64| (1.0..1.2).contains(&t) || (2.5..2.7).contains(&t);
+        assert!(markers(&points(4.0, adc2(slow))).is_empty());
+        let long = |t: f64| (1.0..2.0).contains(&t) || (2.1..2.2).contains(&t);
+        assert!(markers(&points(3.0, adc2(long))).is_empty());
+
+        // while riding
+        let mut riding = points(3.0, adc2(double_tap));
+        for point in riding.iter_mut() {
+            point.speed = 20.0;
+        }
+        assert!(markers(&riding).is_empty());
+    }
+
+    #[test]
+    fn roll_spike() {
+        let roll = |rolled: fn(f64) -> bool| {
+            move |time| DataPoint {
+                roll: Some(if rolled(time) { -45.0 } else { 1.0 }),
+                ..Default::default()
+            }
+        };
+        assert_eq!(
+            markers(&points(5.0, roll(|t| (3.0..3.2).contains(&t)))),
+            vec![Marker {
+                gesture: Gesture::RollSpike,
+                time: 3.0
+            }]
+        );
+
+        // laid on its side
+        assert!(markers(&points(5.0, roll(|t| (1.0..4.0).contains(&t)))).is_empty());
+    }
+
+    #[test]
+    fn first_marker() {
+        let mut samples = points(3.0, adc2(double_tap)).into_iter().map(Ok);
+        let (marker, read) = find_marker(&mut samples, 0.0);
+        assert_eq!(marker.map(|marker| marker.time), Some(1.0));
+        // read up to the end of the second tap, keeping from just before the first
+        assert_eq!(samples.count(), 25);
+        assert_eq!(read.len(), 32);
+        assert!(read[0].as_ref().unwrap().time < 1.0);
+
+        // late in the ride, starting the video 5 s before it
+        let mut samples = points(610.0, adc2(|t| double_tap(t - 600.0)))
+            .into_iter()
+            .map(Ok);
+        let (marker, read) = find_marker(&mut samples, -5.0);
+        assert_eq!(marker.map(|marker| marker.time), Some(601.0));
+        assert_eq!(read.len(), 132);
+        assert!(read[0].as_ref().unwrap().time < 596.0);
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::str::FromStr;
+
+use crate::bail;
+use crate::err::Result;
+use crate::input::{parse_datetime, DataPoint};
+
+/// Speed (in km/h) below which the board is taken to be idle, for logs without a state.
+const IDLE_SPEED: f32 = 1.0;
+
+/// Seconds of idle time kept either side of the riding, so the video doesn't start or end abruptly.
+const PADDING: f64 = 2.0;
+
+/// A point in the ride to trim the video at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    /// Seconds since the start of the ride.
+    Seconds(f64),
+    /// Wall clock time, in milliseconds since the unix epoch.
+    Datetime(i64),
+}
+
+impl Bound {
+    /// Seconds since the start of a ride which started at `start_time`.
+    pub fn seconds(&self, start_time: Option<u64>) -> Result<f64> {
+        match (self, start_time) {
+            (Bound::Seconds(seconds), _) => Ok(*seconds),
+            (Bound::Datetime(ms), Some(start_time)) => Ok((ms - start_time as i64) as f64 / 1000.0),
+            (Bound::Datetime(_), None) => {
+                bail!("the log has no start time, so can't be trimmed at a date and time")
+            }
+        }
+    }
+}
+
+impl FromStr for Bound {
+    type Err = String;
+
+    /// Parses seconds (`90.5`), minutes and seconds (`1:30`), hours, minutes and seconds
+    /// (`1:02:03`), or a date and time (`2024-09-16T09:36:49Z`).
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid time {}, expected seconds, [HH:]MM:SS, or a date and time",
+                s
+            )
+        };
+
+        if s.contains(['T', ' ']) {
+            return parse_datetime(s).map(Bound::Datetime).ok_or_else(invalid);
+        }
+
+        let mut seconds = 0.0;
+        for part in s.split(':') {
+            match part.parse::<f64>() {
+                Ok(value) if value >= 0.0 => seconds = seconds * 60.0 + value,
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(Bound::Seconds(seconds))
+    }
+}
+
+/// Whether the rider is on the board, from the float package's state where the log has it.
+fn is_riding(point: &DataPoint) -> bool {
+    match (point.state_code, &point.state) {
+        // the running states, from plain riding to flywheel mode
+        (Some(code), _) => (1..=5).contains(&code),
+        (None, Some(state)) => state.starts_with("RIDING") || state.starts_with("RUNNING"),
+        (None, None) => point.speed.abs() >= IDLE_SPEED,
+    }
+}
+
+/// Drops the samples outside `start..=end` seconds, and optionally the time the board sat idle
+/// before and after the riding.
+pub struct Trim<I> {
+    samples: I,
+    start: f64,
+    end: f64,
+    idle: bool,
+    /// Idle samples held back until it's known whether riding follows them.
+    held: VecDeque<DataPoint>,
+    ready: VecDeque<DataPoint>,
+    /// Times of the first and last samples in range, and of the first and last riding.
+    first: Option<f64>,
+    last: Option<f64>,
+    first_riding: Option<f64>,
+    last_riding: Option<f64>,
+    exhausted: bool,
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Trim<I> {
+    pub fn new(samples: I, start: Option<f64>, end: Option<f64>, idle: bool) -> Self {
+        Trim {
+            samples,
+            start: start.unwrap_or(f64::NEG_INFINITY),
+            end: end.unwrap_or(f64::INFINITY),
+            idle,
+            held: VecDeque::new(),
+            ready: VecDeque::new(),
+            first: None,
+            last: None,
+            first_riding: None,
+            last_riding: None,
+            exhausted: false,
+        }
+    }
+
+    /// Releases the idle samples left at the end of the ride.
+    fn finish(&mut self) {
+        self.exhausted = true;
+        if !self.idle {
+            return;
+        }
+
+        let (Some(first), Some(last)) = (self.first, self.last) else {
+            return;
+        };
+        let held = mem::take(&mut self.held);
+        match (self.first_riding, self.last_riding) {
+            (Some(first_riding), Some(last_riding)) => {
+                self.ready
+                    .extend(held.into_iter().filter(|p| p.time <= last_riding + PADDING));
+                eprintln!(
+                    "Trimmed {:.1} s of idle time from the start and {:.1} s from the end",
+                    (first_riding - PADDING - first).max(0.0),
+                    (last - last_riding - PADDING).max(0.0)
+                );
+            }
+            _ => {
+                eprintln!("Warning: no riding found, so no idle time was trimmed");
+                self.ready.extend(held);
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<DataPoint>>> Iterator for Trim<I> {
+    type Item = Result<DataPoint>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(point) = self.ready.pop_front() {
+                return Some(Ok(point));
+            }
+            if self.exhausted {
+                return None;
+            }
+
+            let point = match self.samples.next() {
+                Some(Ok(point)) => point,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.finish();
+                    continue;
+                }
+            };
+            if point.time < self.start {
+                continue;
+            }
+            if point.time > self.end {
+                self.finish();
+                continue;
+            }
+
+            self.first.get_or_insert(point.time);
+            self.last = Some(point.time);
+            if !self.idle {
+                return Some(Ok(point));
+            }
+
+            if is_riding(&point) {
+                if self.first_riding.is_none() {
+                    self.first_riding = Some(point.time);
+                    self.held.retain(|p| p.time >= point.time - PADDING);
+                }
+                self.last_riding = Some(point.time);
+                self.ready.extend(self.held.drain(..));
+                self.ready.push_back(point);
+            } else {
+                self.held.push_back(point);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trim(
+        points: &[(f64, f32, Option<u8>)],
+        start: Option<f64>,
+        end: Option<f64>,
+        idle: bool,
+    ) -> Vec<f64> {
+        let samples = points
+            .iter()
+            .map(|(time, speed, state_code)| {
+                Ok(DataPoint {
+                    time: *time,
+                    speed: *speed,
+                    state_code: *state_code,
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Trim::new(samples.into_iter(), start, end, idle)
+            .map(|point| point.unwrap().time)
+            .collect()
+    }
+
+    #[test]
+    fn range() {
+        let points = (0..10).map(|t| (t as f64, 10.0, None)).collect::<Vec<_>>();
+        assert_eq!(
+            trim(&points, Some(2.5), Some(5.0), false),
+            vec![3.0, 4.0, 5.0]
+        );
+        assert_eq!(trim(&points, None, Some(1.0), false), vec![0.0, 1.0]);
+        assert_eq!(trim(&points, None, None, false).len(), 10);
+    }
+
+    #[test]
+    fn idle() {
+        // idle for 10 seconds either side of the riding, and briefly in the middle
+        let points = (0..40)
+            .map(|t| {
+                let riding = (10..30).contains(&t) && t != 20;
+                (t as f64, if riding { 10.0 } else { 0.0 }, None)
+            })
+            .collect::<Vec<_>>();
+        let times = trim(&points, None, None, true);
+        assert_eq!(times.first(), Some(&8.0));
+        assert_eq!(times.last(), Some(&31.0));
+        assert_eq!(times.len(), 24);
+
+        // the state is used over speed
+        let points = [
+            (0.0, 0.0, Some(0)),
+            (5.0, 0.0, Some(1)),
+            (6.0, 0.0, Some(1)),
+            (10.0, 20.0, Some(9)),
+        ];
+        assert_eq!(trim(&points, None, None, true), vec![5.0, 6.0]);
+
+        // nothing is trimmed without riding
+        let points = [(0.0, 0.0, None), (1.0, 0.0, None)];
+        assert_eq!(trim(&points, None, None, true), vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn bounds() {
+        assert_eq!("90.5".parse(), Ok(Bound::Seconds(90.5)));
+        assert_eq!("1:30".parse(), Ok(Bound::Seconds(90.0)));
+        assert_eq!("1:02:03".parse(), Ok(Bound::Seconds(3723.0)));
+        assert_eq!("1970-01-01T00:01:00Z".parse(), Ok(Bound::Datetime(60_000)));
+        assert!("-5".parse::<Bound>().is_err());
+        assert!("1:xx".parse::<Bound>().is_err());
+
+        let bound = Bound::Datetime(60_000);
+        assert_eq!(bound.seconds(Some(15_000)).unwrap(), 45.0);
+        assert!(bound.seconds(None).is_err());
+    }
+}